@@ -36,7 +36,8 @@ fn main() {
     
     module.verify().unwrap();
     
-    let ee = JitEngine::new(&module, JitOptions {opt_level: 0}).unwrap();
+    let ee = JitEngine::new(module, JitOptions::new().opt_level(0)).unwrap();
+    let func = ee.find_function("fib").unwrap();
     ee.with_function(func, |fib: extern fn(u64) -> u64| {
         for i in 0..10 {
             println!("fib {} = {}", i, fib(i))
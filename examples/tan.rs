@@ -18,7 +18,8 @@ fn main() {
     builder.create_ret(value);
     module.verify().unwrap();
     
-    let ee = JitEngine::new(&module, JitOptions {opt_level: 0}).unwrap();
+    let ee = JitEngine::new(module, JitOptions::new().opt_level(0)).unwrap();
+    let func = ee.find_function("tan").unwrap();
     ee.with_function(func, |tan:extern fn(f64) -> f64| {
         for i in 0..10 {
             let i = i as f64;
@@ -0,0 +1,582 @@
+use std::collections::HashMap;
+
+use ffi::prelude::LLVMBasicBlockRef;
+
+use block::BasicBlock;
+use value::Function;
+
+
+/// The control flow graph of a function, with its blocks numbered in order.
+struct Cfg<'a>
+{
+  blocks: Vec<&'a BasicBlock>,
+  index: HashMap<LLVMBasicBlockRef, usize>,
+  succs: Vec<Vec<usize>>,
+  preds: Vec<Vec<usize>>
+}
+
+impl<'a> Cfg<'a>
+{
+  fn new(func: &'a Function) -> Cfg<'a>
+  {
+    let blocks: Vec<&'a BasicBlock> = func.basic_blocks().collect();
+    let index: HashMap<LLVMBasicBlockRef, usize> = blocks.iter()
+      .enumerate()
+      .map(|(i, &block)| (block.into(), i))
+      .collect();
+    let mut succs = vec![Vec::new(); blocks.len()];
+    let mut preds = vec![Vec::new(); blocks.len()];
+    for (i, block) in blocks.iter().enumerate() {
+      for succ in block.successors() {
+        let succ: LLVMBasicBlockRef = succ.into();
+        let j = index[&succ];
+        succs[i].push(j);
+        preds[j].push(i);
+      }
+    }
+    Cfg {
+      blocks: blocks,
+      index: index,
+      succs: succs,
+      preds: preds
+    }
+  }
+
+  fn index_of(&self, block: &BasicBlock) -> Option<usize>
+  {
+    let ptr: LLVMBasicBlockRef = block.into();
+    self.index.get(&ptr).cloned()
+  }
+}
+
+
+/// A dominator or post-dominator tree over the basic blocks of a function.
+///
+/// A block `a` dominates a block `b` when every path from the entry to `b` goes through
+/// `a`, and post-dominates it when every path from `b` to an exit goes through `a`.
+/// Blocks that can't be reached (or can't reach an exit, for post-dominators) are not in
+/// the tree.
+///
+/// This is a snapshot of the function when it was computed, so it must be computed again
+/// after the control flow changes.
+pub struct DominatorTree<'a>
+{
+  cfg: Cfg<'a>,
+  post: bool,
+  /// The immediate dominator of each block, where the root is its own and
+  /// `cfg.blocks.len()` is the virtual exit joining every exit of a post-dominator tree.
+  idom: Vec<Option<usize>>,
+  children: Vec<Vec<usize>>,
+  roots: Vec<usize>,
+  /// The pre-order and post-order numbers of each block in the tree.
+  order: Vec<(usize, usize)>,
+  frontier: Vec<Vec<usize>>
+}
+
+impl<'a> DominatorTree<'a>
+{
+  /// Compute the dominator tree of `func`.
+  pub fn new(func: &'a Function) -> DominatorTree<'a>
+  {
+    DominatorTree::compute(Cfg::new(func), false)
+  }
+
+  /// Compute the post-dominator tree of `func`.
+  pub fn new_post(func: &'a Function) -> DominatorTree<'a>
+  {
+    DominatorTree::compute(Cfg::new(func), true)
+  }
+
+  fn compute(cfg: Cfg<'a>, post: bool) -> DominatorTree<'a>
+  {
+    let num = cfg.blocks.len();
+    // The graph is walked backwards for post-dominators, starting from a virtual
+    // exit node numbered `num` whose successors are the real exits.
+    let (fwd, back) = if post {
+      let mut fwd = cfg.preds.clone();
+      let mut back = cfg.succs.clone();
+      let exits: Vec<usize> = (0..num).filter(|&i| cfg.succs[i].is_empty()).collect();
+      for &exit in &exits {
+        back[exit].push(num);
+      }
+      fwd.push(exits);
+      back.push(Vec::new());
+      (fwd, back)
+    } else {
+      (cfg.succs.clone(), cfg.preds.clone())
+    };
+    let root = if post { Some(num) } else if num > 0 { Some(0) } else { None };
+    let total = fwd.len();
+
+    // Number the nodes in reverse post-order.
+    let mut rpo = Vec::with_capacity(total);
+    if let Some(root) = root {
+      let mut visited = vec![false; total];
+      let mut stack = vec![(root, 0)];
+      visited[root] = true;
+      while let Some(&mut (node, ref mut next)) = stack.last_mut() {
+        if *next < fwd[node].len() {
+          let succ = fwd[node][*next];
+          *next += 1;
+          if !visited[succ] {
+            visited[succ] = true;
+            stack.push((succ, 0));
+          }
+        } else {
+          rpo.push(node);
+          stack.pop();
+        }
+      }
+      rpo.reverse();
+    }
+    let mut rpo_num = vec![usize::max_value(); total];
+    for (i, &node) in rpo.iter().enumerate() {
+      rpo_num[node] = i;
+    }
+
+    // Cooper, Harvey and Kennedy's iterative algorithm.
+    let mut idom: Vec<Option<usize>> = vec![None; total];
+    if let Some(root) = root {
+      idom[root] = Some(root);
+    }
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for &node in rpo.iter().skip(1) {
+        let mut new_idom = None;
+        for &pred in &back[node] {
+          if idom[pred].is_none() {
+            continue
+          }
+          new_idom = Some(match new_idom {
+            None => pred,
+            Some(other) => {
+              let (mut a, mut b) = (pred, other);
+              while a != b {
+                while rpo_num[a] > rpo_num[b] {
+                  a = idom[a].unwrap();
+                }
+                while rpo_num[b] > rpo_num[a] {
+                  b = idom[b].unwrap();
+                }
+              }
+              a
+            }
+          });
+        }
+        if new_idom.is_some() && idom[node] != new_idom {
+          idom[node] = new_idom;
+          changed = true;
+        }
+      }
+    }
+
+    let mut children = vec![Vec::new(); total];
+    for &node in rpo.iter().skip(1) {
+      children[idom[node].unwrap()].push(node);
+    }
+
+    let mut order = vec![(0, 0); total];
+    let (mut pre, mut post_num) = (0, 0);
+    if let Some(root) = root {
+      let mut stack = vec![(root, 0)];
+      order[root].0 = pre;
+      pre += 1;
+      while let Some(&mut (node, ref mut next)) = stack.last_mut() {
+        if *next < children[node].len() {
+          let child = children[node][*next];
+          *next += 1;
+          order[child].0 = pre;
+          pre += 1;
+          stack.push((child, 0));
+        } else {
+          order[node].1 = post_num;
+          post_num += 1;
+          stack.pop();
+        }
+      }
+    }
+
+    let mut frontier = vec![Vec::new(); total];
+    for &node in &rpo {
+      let reached: Vec<usize> = back[node].iter().cloned().filter(|&p| idom[p].is_some()).collect();
+      if reached.len() < 2 {
+        continue
+      }
+      for pred in reached {
+        let mut runner = pred;
+        while Some(runner) != idom[node] {
+          if !frontier[runner].contains(&node) {
+            frontier[runner].push(node);
+          }
+          runner = idom[runner].unwrap();
+        }
+      }
+    }
+
+    // The virtual exit isn't a block, so its children are the roots.
+    let roots = match root {
+      Some(root) if post => children[root].clone(),
+      Some(root) => vec![root],
+      None => Vec::new()
+    };
+
+    DominatorTree {
+      cfg: cfg,
+      post: post,
+      idom: idom,
+      children: children,
+      roots: roots,
+      order: order,
+      frontier: frontier
+    }
+  }
+
+  fn block_index(&self, block: &BasicBlock) -> Option<usize>
+  {
+    self.cfg.index_of(block).and_then(|i| self.idom[i].map(|_| i))
+  }
+
+  fn to_blocks(&self, nodes: &[usize]) -> Vec<&'a BasicBlock>
+  {
+    nodes.iter()
+      .filter(|&&node| node < self.cfg.blocks.len())
+      .map(|&node| self.cfg.blocks[node])
+      .collect()
+  }
+
+  /// Returns true if this is a post-dominator tree.
+  pub fn is_post(&self) -> bool
+  {
+    self.post
+  }
+
+  /// Returns the roots of this tree, which is the entry block for a dominator tree and
+  /// every exit block for a post-dominator tree.
+  pub fn roots(&self) -> Vec<&'a BasicBlock>
+  {
+    self.to_blocks(&self.roots)
+  }
+
+  /// Returns true if `block` is in this tree.
+  pub fn contains(&self, block: &BasicBlock) -> bool
+  {
+    self.block_index(block).is_some()
+  }
+
+  /// Returns the immediate dominator of `block`, or `None` if it is a root or not in the tree.
+  pub fn idom(&self, block: &BasicBlock) -> Option<&'a BasicBlock>
+  {
+    self.block_index(block)
+      .and_then(|i| self.idom[i])
+      .and_then(|node| self.to_blocks(&[node]).pop())
+      .and_then(|idom| if idom == block { None } else { Some(idom) })
+  }
+
+  /// Returns the blocks that `block` immediately dominates.
+  pub fn children(&self, block: &BasicBlock) -> Vec<&'a BasicBlock>
+  {
+    match self.block_index(block) {
+      Some(i) => self.to_blocks(&self.children[i]),
+      None => Vec::new()
+    }
+  }
+
+  /// Returns true if `a` dominates `b`, which includes when they are the same block.
+  pub fn dominates(&self, a: &BasicBlock, b: &BasicBlock) -> bool
+  {
+    match (self.block_index(a), self.block_index(b)) {
+      (Some(a), Some(b)) => {
+        let (a, b) = (self.order[a], self.order[b]);
+        a.0 <= b.0 && a.1 >= b.1
+      },
+      _ => false
+    }
+  }
+
+  /// Returns true if `a` dominates `b` and they are different blocks.
+  pub fn strictly_dominates(&self, a: &BasicBlock, b: &BasicBlock) -> bool
+  {
+    a != b && self.dominates(a, b)
+  }
+
+  /// Returns the dominance frontier of `block`, which is every block where its
+  /// dominance stops.
+  pub fn frontier(&self, block: &BasicBlock) -> Vec<&'a BasicBlock>
+  {
+    match self.block_index(block) {
+      Some(i) => self.to_blocks(&self.frontier[i]),
+      None => Vec::new()
+    }
+  }
+}
+
+
+/// A natural loop: a header that dominates every block in the loop, and the blocks that
+/// can reach one of the back edges to the header without going through it.
+pub struct Loop<'a>
+{
+  header: &'a BasicBlock,
+  latches: Vec<&'a BasicBlock>,
+  blocks: Vec<&'a BasicBlock>,
+  exiting: Vec<&'a BasicBlock>,
+  exits: Vec<&'a BasicBlock>,
+  parent: Option<usize>,
+  depth: usize
+}
+
+impl<'a> Loop<'a>
+{
+  /// Returns the block that every iteration of this loop starts at.
+  pub fn header(&self) -> &'a BasicBlock
+  {
+    self.header
+  }
+
+  /// Returns the blocks that branch back to the header.
+  pub fn latches(&self) -> &[&'a BasicBlock]
+  {
+    &self.latches
+  }
+
+  /// Returns the blocks in this loop, including those of nested loops, header first.
+  pub fn blocks(&self) -> &[&'a BasicBlock]
+  {
+    &self.blocks
+  }
+
+  /// Returns the blocks in this loop that can branch out of it.
+  pub fn exiting_blocks(&self) -> &[&'a BasicBlock]
+  {
+    &self.exiting
+  }
+
+  /// Returns the blocks outside this loop that it can branch to.
+  pub fn exits(&self) -> &[&'a BasicBlock]
+  {
+    &self.exits
+  }
+
+  /// Returns true if `block` is in this loop.
+  pub fn contains(&self, block: &BasicBlock) -> bool
+  {
+    self.blocks.iter().any(|&other| other == block)
+  }
+
+  /// Returns how deeply nested this loop is, where an outermost loop has a depth of 1.
+  pub fn depth(&self) -> usize
+  {
+    self.depth
+  }
+}
+
+
+/// The natural loops of a function and how they are nested.
+pub struct LoopInfo<'a>
+{
+  loops: Vec<Loop<'a>>
+}
+
+impl<'a> LoopInfo<'a>
+{
+  /// Find the loops in the function that `dom`, which must not be a post-dominator tree,
+  /// was computed for.
+  pub fn new(dom: &DominatorTree<'a>) -> LoopInfo<'a>
+  {
+    assert!(!dom.is_post(), "loops must be found with a dominator tree, not a post-dominator tree");
+    let cfg = &dom.cfg;
+    let mut headers: Vec<(usize, Vec<usize>)> = Vec::new();
+    for (latch, succs) in cfg.succs.iter().enumerate() {
+      for &header in succs {
+        if dom.dominates(cfg.blocks[header], cfg.blocks[latch]) {
+          match headers.iter().position(|&(h, _)| h == header) {
+            Some(pos) => headers[pos].1.push(latch),
+            None => headers.push((header, vec![latch]))
+          }
+        }
+      }
+    }
+
+    let mut bodies: Vec<Vec<bool>> = Vec::new();
+    for &(header, ref latches) in &headers {
+      let mut body = vec![false; cfg.blocks.len()];
+      body[header] = true;
+      let mut stack = latches.clone();
+      while let Some(node) = stack.pop() {
+        if !body[node] {
+          body[node] = true;
+          stack.extend(cfg.preds[node].iter().cloned().filter(|&p| dom.idom[p].is_some()));
+        }
+      }
+      bodies.push(body);
+    }
+
+    let sizes: Vec<usize> = bodies.iter().map(|body| body.iter().filter(|&&b| b).count()).collect();
+    let mut loops = Vec::new();
+    for (i, &(header, ref latches)) in headers.iter().enumerate() {
+      // The parent is the smallest other loop containing this header.
+      let parent = (0..headers.len())
+        .filter(|&j| j != i && bodies[j][header] && sizes[j] > sizes[i])
+        .min_by_key(|&j| sizes[j]);
+      let mut blocks = vec![cfg.blocks[header]];
+      let mut exiting = Vec::new();
+      let mut exits = Vec::new();
+      for node in 0..cfg.blocks.len() {
+        if !bodies[i][node] {
+          continue
+        }
+        if node != header {
+          blocks.push(cfg.blocks[node]);
+        }
+        let outside: Vec<usize> = cfg.succs[node].iter().cloned().filter(|&s| !bodies[i][s]).collect();
+        if !outside.is_empty() {
+          exiting.push(cfg.blocks[node]);
+        }
+        for succ in outside {
+          if !exits.contains(&cfg.blocks[succ]) {
+            exits.push(cfg.blocks[succ]);
+          }
+        }
+      }
+      loops.push(Loop {
+        header: cfg.blocks[header],
+        latches: latches.iter().map(|&l| cfg.blocks[l]).collect(),
+        blocks: blocks,
+        exiting: exiting,
+        exits: exits,
+        parent: parent,
+        depth: 0
+      });
+    }
+
+    for i in 0..loops.len() {
+      let mut depth = 1;
+      let mut cur = loops[i].parent;
+      while let Some(parent) = cur {
+        depth += 1;
+        cur = loops[parent].parent;
+      }
+      loops[i].depth = depth;
+    }
+
+    LoopInfo {
+      loops: loops
+    }
+  }
+
+  /// Returns every loop in the function, outer loops before the loops nested in them.
+  pub fn loops(&self) -> Vec<&Loop<'a>>
+  {
+    let mut loops: Vec<&Loop<'a>> = self.loops.iter().collect();
+    loops.sort_by_key(|l| l.depth);
+    loops
+  }
+
+  /// Returns the loop that `lp` is nested in, or `None` if it is an outermost loop.
+  pub fn parent(&self, lp: &Loop<'a>) -> Option<&Loop<'a>>
+  {
+    lp.parent.map(|parent| &self.loops[parent])
+  }
+
+  /// Returns the innermost loop that contains `block`, or `None` if it is not in a loop.
+  pub fn loop_for(&self, block: &BasicBlock) -> Option<&Loop<'a>>
+  {
+    self.loops.iter()
+      .filter(|lp| lp.contains(block))
+      .max_by_key(|lp| lp.depth)
+  }
+
+  /// Returns the number of loops that contain `block`.
+  pub fn loop_depth(&self, block: &BasicBlock) -> usize
+  {
+    self.loop_for(block).map(|lp| lp.depth).unwrap_or(0)
+  }
+
+  /// Returns true if `block` is the header of a loop.
+  pub fn is_header(&self, block: &BasicBlock) -> bool
+  {
+    self.loops.iter().any(|lp| lp.header == block)
+  }
+}
+
+
+/// The analyses of a function, computed when they are first asked for and kept until
+/// they are invalidated.
+///
+/// Nothing is invalidated automatically, so call `invalidate` after changing the control
+/// flow of the function.
+///
+/// ```rust
+/// use llvm::*;
+/// let ctx = Context::new();
+/// let module = Module::new("analysis", &ctx);
+/// let func = module.add_function("nothing", Type::get::<fn() -> ()>(&ctx));
+/// let entry = func.append("entry");
+/// Builder::new(&ctx).position_at_end(entry);
+/// let mut analyses = FunctionAnalyses::new(func);
+/// assert!(analyses.dominators().dominates(entry, entry));
+/// assert!(analyses.loops().loops().is_empty());
+/// ```
+pub struct FunctionAnalyses<'a>
+{
+  function: &'a Function,
+  dominators: Option<DominatorTree<'a>>,
+  post_dominators: Option<DominatorTree<'a>>,
+  loops: Option<LoopInfo<'a>>
+}
+
+impl<'a> FunctionAnalyses<'a>
+{
+  /// Make an empty cache of analyses for `function`.
+  pub fn new(function: &'a Function) -> FunctionAnalyses<'a>
+  {
+    FunctionAnalyses {
+      function: function,
+      dominators: None,
+      post_dominators: None,
+      loops: None
+    }
+  }
+
+  /// Returns the function being analysed.
+  pub fn function(&self) -> &'a Function
+  {
+    self.function
+  }
+
+  /// Returns the dominator tree, computing it if it isn't cached.
+  pub fn dominators(&mut self) -> &DominatorTree<'a>
+  {
+    if self.dominators.is_none() {
+      self.dominators = Some(DominatorTree::new(self.function));
+    }
+    self.dominators.as_ref().unwrap()
+  }
+
+  /// Returns the post-dominator tree, computing it if it isn't cached.
+  pub fn post_dominators(&mut self) -> &DominatorTree<'a>
+  {
+    if self.post_dominators.is_none() {
+      self.post_dominators = Some(DominatorTree::new_post(self.function));
+    }
+    self.post_dominators.as_ref().unwrap()
+  }
+
+  /// Returns the loop information, computing it and the dominator tree if they aren't cached.
+  pub fn loops(&mut self) -> &LoopInfo<'a>
+  {
+    if self.loops.is_none() {
+      let loops = LoopInfo::new(self.dominators());
+      self.loops = Some(loops);
+    }
+    self.loops.as_ref().unwrap()
+  }
+
+  /// Drop every cached analysis, so they are computed again when asked for.
+  pub fn invalidate(&mut self)
+  {
+    self.dominators = None;
+    self.post_dominators = None;
+    self.loops = None;
+  }
+}
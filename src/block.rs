@@ -1,8 +1,15 @@
-use ffi::core;
+use std::fmt;
+use std::marker::PhantomData;
+
+use ffi::{core, LLVMBasicBlock};
 use ffi::prelude::LLVMBasicBlockRef;
 
-use util;
-use value::Value;
+use builder::Builder;
+use context::GetContext;
+use instr::Instruction;
+use phi::PhiNode;
+use util::{self, CastFrom};
+use value::{Function, Links, Value, ValueIter};
 
 
 /// A container of instructions that execute sequentially.
@@ -17,6 +24,174 @@ impl BasicBlock
     unsafe { util::ptr_to_null(core::LLVMGetBasicBlockParent(self.into())) }
   }
   
+  /// Returns the function this basic block is in, or `None` if it isn't in one.
+  pub(crate) fn get_function(&self) -> Option<&Function> 
+  {
+    unsafe { util::ptr_to_null(core::LLVMGetBasicBlockParent(self.into())) }
+  }
+  
+  /// Returns the name of this basic block, or `None` if it lacks a name.
+  pub fn get_name(&self) -> Option<&str>
+  {
+    self.as_value().get_name()
+  }
+  
+  /// Returns this basic block as a value, such as for use as a branch target.
+  pub fn as_value(&self) -> &Value
+  {
+    unsafe { core::LLVMBasicBlockAsValue(self.into()) }.into()
+  }
+  
+  /// Returns the block after this one in its function, or `None` if this is the last.
+  pub fn get_next(&self) -> Option<&BasicBlock>
+  {
+    unsafe { util::ptr_to_null(core::LLVMGetNextBasicBlock(self.into())) }
+  }
+  
+  /// Returns the block before this one in its function, or `None` if this is the first.
+  pub fn get_previous(&self) -> Option<&BasicBlock>
+  {
+    unsafe { util::ptr_to_null(core::LLVMGetPreviousBasicBlock(self.into())) }
+  }
+  
+  /// Returns the first instruction in this block, or `None` if it is empty.
+  pub fn first_instruction(&self) -> Option<&Instruction>
+  {
+    unsafe { util::ptr_to_null(core::LLVMGetFirstInstruction(self.into())) }
+  }
+  
+  /// Returns the last instruction in this block, or `None` if it is empty.
+  pub fn last_instruction(&self) -> Option<&Instruction>
+  {
+    unsafe { util::ptr_to_null(core::LLVMGetLastInstruction(self.into())) }
+  }
+  
+  /// Returns the instruction that ends this block, or `None` if it is not terminated yet.
+  pub fn get_terminator(&self) -> Option<&Instruction>
+  {
+    unsafe { util::ptr_to_null(core::LLVMGetBasicBlockTerminator(self.into())) }
+  }
+  
+  /// Iterate through the instructions in this block in order.
+  pub fn instructions(&self) -> ValueIter<&Instruction>
+  {
+    ValueIter::new_double_ended(
+      unsafe { core::LLVMGetFirstInstruction(self.into()) },
+      unsafe { core::LLVMGetLastInstruction(self.into()) },
+      core::LLVMGetNextInstruction,
+      core::LLVMGetPreviousInstruction)
+  }
+  
+  /// Returns the blocks the terminator of this block can branch to.
+  ///
+  /// A block that is branched to along several edges is only returned once.
+  pub fn successors(&self) -> Vec<&BasicBlock> 
+  {
+    let mut blocks: Vec<&BasicBlock> = Vec::new();
+    if let Some(term) = self.get_terminator() {
+      for index in 0..term.num_successors() {
+        let block = term.get_successor(index).unwrap();
+        if !blocks.contains(&block) {
+          blocks.push(block);
+        }
+      }
+    }
+    blocks
+  }
+  
+  /// Returns the blocks whose terminators can branch to this block.
+  ///
+  /// A block that branches here along several edges is only returned once.
+  pub fn predecessors(&self) -> Vec<&BasicBlock> 
+  {
+    let mut blocks: Vec<&BasicBlock> = Vec::new();
+    for user in self.as_value().users() {
+      if let Some(instr) = Instruction::cast(user) {
+        match instr.parent() {
+          Some(block) if instr.is_terminator() && !blocks.contains(&block) => blocks.push(block),
+          _ => ()
+        }
+      }
+    }
+    blocks
+  }
+  
+  /// Returns true if this block can be reached from the entry block of its function.
+  pub fn is_reachable(&self) -> bool 
+  {
+    match self.get_function() {
+      Some(func) => !func.unreachable_blocks().contains(&self),
+      None => false
+    }
+  }
+  
+  /// Create a new block with the name given, placed before this one in its function.
+  pub fn insert_before(&self, name: &str) -> &BasicBlock 
+  {
+    util::with_cstr(name, |ptr| unsafe {
+      let context = self.as_value().get_context();
+      core::LLVMInsertBasicBlockInContext(context.into(), self.into(), ptr).into()
+    })
+  }
+  
+  /// Split this block in two at `instr`, which must be in this block.
+  ///
+  /// Every instruction from `instr` to the end of this block is moved into a new block
+  /// with the name given, placed after this one, and this block gets an unconditional
+  /// branch to the new block. PHI nodes in the successors are updated to expect the new
+  /// block instead of this one. The new block is returned.
+  pub fn split_at(&self, instr: &Instruction, name: &str) -> &BasicBlock 
+  {
+    assert!(instr.parent() == Some(self), "cannot split {:?} at an instruction outside it", self.get_name());
+    let new_block = match self.get_next() {
+      Some(next) => next.insert_before(name),
+      None => {
+        let func = self.get_function().unwrap();
+        func.append(name)
+      }
+    };
+    
+    let builder = Builder::new(self.as_value().get_context());
+    builder.position_at_end(new_block);
+    let mut cur = Some(instr);
+    while let Some(instr) = cur {
+      cur = unsafe { util::ptr_to_null(core::LLVMGetNextInstruction(instr.into())) };
+      builder.insert(unsafe { instr.remove_from_parent() });
+    }
+    
+    for succ in new_block.successors() {
+      succ.replace_phi_block(self, new_block);
+    }
+    
+    builder.position_at_end(self);
+    builder.create_br(new_block);
+    new_block
+  }
+  
+  /// Rebuild the PHI nodes at the start of this block that expect `old` to expect `new`.
+  fn replace_phi_block(&self, old: &BasicBlock, new: &BasicBlock) 
+  {
+    let builder = Builder::new(self.as_value().get_context());
+    let phis: Vec<_> = self.instructions().filter_map(|instr| PhiNode::cast(instr)).collect();
+    for phi in phis {
+      let count = phi.count_incoming();
+      if !(0..count).any(|index| phi.get_incoming_block(index) == old) {
+        continue
+      }
+      
+      builder.position_at(self, phi);
+      let name = phi.get_name().unwrap_or("").to_owned();
+      let new_phi = builder.create_phi(phi.get_type(), &name);
+      for index in 0..count {
+        let block = phi.get_incoming_block(index);
+        let block = if block == old { new } else { block };
+        new_phi.add_incoming(phi.get_incoming_value(index), block);
+      }
+      phi.replace_all_uses_with(new_phi);
+      unsafe { Instruction::cast(phi).unwrap().remove_from_parent() }.erase()
+    }
+  }
+  
   /// Move this basic block after the `other` basic block in its function.
   pub fn move_after(&self, other: &BasicBlock) 
   {
@@ -38,9 +213,57 @@ impl BasicBlock
   /// Delete this basic block.
   ///
   /// This is unsafe because there should be no other reference to this, but
-  /// this can't be guranteed using Rust semantics.
+  /// this can't be guaranteed using Rust semantics.
   pub unsafe fn delete(&self) 
   {
     core::LLVMDeleteBasicBlock(self.into())
   }
 }
+
+
+impl fmt::Debug for BasicBlock 
+{
+  fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result 
+  {
+    fmt::Debug::fmt(self.as_value(), fmt)
+  }
+}
+
+
+/// An iterator through the basic blocks of a function.
+#[derive(Copy, Clone)]
+pub struct BlockIter<'a> {
+  links  : Links<LLVMBasicBlock>,
+  marker : PhantomData<&'a ()>,
+}
+
+impl<'a> BlockIter<'a>
+{
+  /// Iterate from `cur` to `last` inclusively.
+  pub fn new(cur: LLVMBasicBlockRef, last: LLVMBasicBlockRef) -> Self
+  {
+    let links = Links::new(cur, last, core::LLVMGetNextBasicBlock, Some(core::LLVMGetPreviousBasicBlock));
+    BlockIter {
+      links: links,
+      marker: PhantomData
+    }
+  }
+}
+
+impl<'a> Iterator for BlockIter<'a>
+{
+  type Item = &'a BasicBlock;
+
+  fn next(&mut self) -> Option<&'a BasicBlock>
+  {
+    self.links.next().map(|block| block.into())
+  }
+}
+
+impl<'a> DoubleEndedIterator for BlockIter<'a>
+{
+  fn next_back(&mut self) -> Option<&'a BasicBlock>
+  {
+    self.links.next_back().map(|block| block.into())
+  }
+}
@@ -1,13 +1,16 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::mem;
 use std::ffi::CString;
 
 use cbox::{CSemiBox, DisposeRef};
 use ffi::prelude::{LLVMBuilderRef, LLVMValueRef};
-use ffi::{core, LLVMBuilder, LLVMRealPredicate, LLVMIntPredicate};
+use ffi::{core, LLVMBuilder};
 use libc::{c_char, c_uint};
 
 use context::Context;
 use block::BasicBlock;
+use instr::DetachedInstruction;
 use ty::Type;
 use value::{Function, Value, Predicate};
 use phi::PhiNode;
@@ -18,6 +21,11 @@ static NULL_NAME:[c_char; 1] = [0];
 pub struct Builder;
 native_ref!(&Builder = LLVMBuilderRef);
 
+thread_local! {
+  /// The builders that verify a function once every block in it is terminated.
+  static VERIFYING: RefCell<HashSet<LLVMBuilderRef>> = RefCell::new(HashSet::new());
+}
+
 macro_rules! bin_op (
   ($name:ident, $func:ident) => (
     pub fn $name(&self, left: &Value, right: &Value) -> &Value 
@@ -70,16 +78,54 @@ impl Builder
     unsafe { core::LLVMPositionBuilderAtEnd(self.into(), block.into()) }
   }
   
+  /// Set whether this builder verifies a function whenever it terminates the last
+  /// unterminated block in it, panicking with the verifier's message if it is broken.
+  ///
+  /// This only has an effect when debug assertions are enabled.
+  pub fn set_verify_functions(&self, enabled: bool) 
+  {
+    VERIFYING.with(|verifying| {
+      let mut verifying = verifying.borrow_mut();
+      if enabled {
+        verifying.insert(self.into());
+      } else {
+        verifying.remove(&self.into());
+      }
+    })
+  }
+  
+  /// Verify the function `term` was built in if that was requested and every block in it
+  /// is now terminated.
+  fn terminated(&self, term: LLVMValueRef) -> &Value 
+  {
+    if cfg!(debug_assertions) && VERIFYING.with(|verifying| verifying.borrow().contains(&self.into())) {
+      if let Some(func) = self.get_insert_block().get_function() {
+        if func.basic_blocks().all(|block| block.get_terminator().is_some()) {
+          if let Err(err) = func.verify() {
+            panic!("{}", err)
+          }
+        }
+      }
+    }
+    term.into()
+  }
+  
+  /// Insert an instruction that was unlinked from its block at the current position.
+  pub fn insert(&self, instr: DetachedInstruction) 
+  {
+    unsafe { core::LLVMInsertIntoBuilder(self.into(), instr.into_raw()) }
+  }
+  
   /// Build an instruction that returns from the function with void.
   pub fn create_ret_void(&self) -> &Value 
   {
-    unsafe { core::LLVMBuildRetVoid(self.into()) }.into()
+    self.terminated(unsafe { core::LLVMBuildRetVoid(self.into()) })
   }
   
   /// Build an instruction that returns from the function with `value`.
   pub fn create_ret(&self, value: &Value) -> &Value 
   {
-    unsafe { core::LLVMBuildRet(self.into(), value.into()) }.into()
+    self.terminated(unsafe { core::LLVMBuildRet(self.into(), value.into()) })
   }
   
   /// Build an instruction that allocates an array with the element type `elem` and the size `size`.
@@ -124,19 +170,19 @@ impl Builder
   /// Build an instruction that branches to the block `dest`.
   pub fn create_br(&self, dest: &BasicBlock) -> &Value 
   {
-    unsafe { core::LLVMBuildBr(self.into(), dest.into()).into() }
+    self.terminated(unsafe { core::LLVMBuildBr(self.into(), dest.into()) })
   }
   
   /// Build an instruction that branches to `if_block` if `cond` evaluates to true, and `else_block` otherwise.
   pub fn create_cond_br(&self, cond: &Value, 
   	                    if_block: &BasicBlock, else_block: Option<&BasicBlock>) -> &Value 
   {
-    unsafe { 
+    self.terminated(unsafe { 
     	core::LLVMBuildCondBr(self.into(), 
     		                    cond.into(), 
     		                    if_block.into(), 
-    		                    mem::transmute(else_block)).into() 
-    }
+    		                    mem::transmute(else_block))
+    })
   }
   
   /// Build an instruction that calls the function `func` with the arguments `args`.
@@ -145,10 +191,11 @@ impl Builder
   pub fn create_call(&self, func: &Function, args: &[&Value]) -> &Value 
   {
     unsafe {
-        let call = core::LLVMBuildCall(self.into(), 
-        	                             func.into(), args.as_ptr() as *mut LLVMValueRef, 
-        	                             args.len() as c_uint, 
-        	                             NULL_NAME.as_ptr());
+        let call = core::LLVMBuildCall2(self.into(), 
+        	                              core::LLVMGlobalGetValueType(func.into()), 
+        	                              func.into(), args.as_ptr() as *mut LLVMValueRef, 
+        	                              args.len() as c_uint, 
+        	                              NULL_NAME.as_ptr());
         core::LLVMSetTailCall(call, 0);
         call.into()
     }
@@ -160,10 +207,11 @@ impl Builder
   pub fn create_tail_call(&self, func: &Function, args: &[&Value]) -> &Value 
   {
     unsafe {
-        let call = core::LLVMBuildCall(self.into(), 
-        	                             func.into(), args.as_ptr() as *mut LLVMValueRef, 
-        	                             args.len() as c_uint, 
-        	                             NULL_NAME.as_ptr());
+        let call = core::LLVMBuildCall2(self.into(), 
+        	                              core::LLVMGlobalGetValueType(func.into()), 
+        	                              func.into(), args.as_ptr() as *mut LLVMValueRef, 
+        	                              args.len() as c_uint, 
+        	                              NULL_NAME.as_ptr());
         core::LLVMSetTailCall(call, 1);
         call.into()
     }
@@ -212,17 +260,29 @@ impl Builder
    	}
   }
   
-  /// Build an instruction that computes the address of a subelement of an aggregate data structure.
+  /// Build an instruction that computes the address of a subelement of an aggregate data structure,
+  /// where `pointer` points to a value of the type `ty`.
   ///
   /// Basically type-safe pointer arithmetic.
-  pub fn create_gep(&self, pointer: &Value, indices: &[&Value]) -> &Value 
+  pub fn create_gep(&self, ty: &Type, pointer: &Value, indices: &[&Value]) -> &Value 
+  {
+    unsafe { 
+    	core::LLVMBuildInBoundsGEP2(self.into(), 
+    	                            ty.into(), 
+    	                            pointer.into(), 
+    	                            indices.as_ptr() as *mut LLVMValueRef, 
+    	                            indices.len() as c_uint, 
+    	                            NULL_NAME.as_ptr()).into() 
+    }
+  }
+  
+  /// Build an instruction that loads a value of the type `ty` from `pointer`.
+  ///
+  /// Pointers don't have pointee types, so the type to load has to be given.
+  pub fn create_load(&self, ty: &Type, pointer: &Value) -> &Value 
   {
     unsafe { 
-    	core::LLVMBuildInBoundsGEP(self.into(), 
-    	                           pointer.into(), 
-    	                           indices.as_ptr() as *mut LLVMValueRef, 
-    	                           indices.len() as c_uint, 
-    	                           NULL_NAME.as_ptr()).into() 
+    	core::LLVMBuildLoad2(self.into(), ty.into(), pointer.into(), NULL_NAME.as_ptr()).into() 
     }
   }
   
@@ -248,11 +308,10 @@ impl Builder
       for case in cases {
         core::LLVMAddCase(switch, case.0.into(), case.1.into());
       }
-      switch.into()
+      self.terminated(switch)
     }
   }
   
-  unary_op!{create_load, LLVMBuildLoad}
   unary_op!{create_neg, LLVMBuildNeg}
   unary_op!{create_not, LLVMBuildNot}
   
@@ -273,39 +332,17 @@ impl Builder
     assert_eq!(lhs_ty, rhs_ty);
     
     if lhs_ty.is_integer() {
-	    let p = match (pred, signed) {
-	    	(Predicate::Equal, _)                  => LLVMIntPredicate::LLVMIntEQ,
-	    	(Predicate::NotEqual, _)               => LLVMIntPredicate::LLVMIntNE,
-	    	(Predicate::LessThan, true)            => LLVMIntPredicate::LLVMIntSLT,
-	    	(Predicate::LessThan, false)           => LLVMIntPredicate::LLVMIntULT,
-	    	(Predicate::LessThanOrEqual, true)     => LLVMIntPredicate::LLVMIntSLE,
-	    	(Predicate::LessThanOrEqual, false)    => LLVMIntPredicate::LLVMIntULE,
-	    	(Predicate::GreaterThan, true)         => LLVMIntPredicate::LLVMIntSGT,
-	    	(Predicate::GreaterThan, false)        => LLVMIntPredicate::LLVMIntUGT,
-	    	(Predicate::GreaterThanOrEqual, true)  => LLVMIntPredicate::LLVMIntSGE,
-	    	(Predicate::GreaterThanOrEqual, false) => LLVMIntPredicate::LLVMIntUGE,
-	    };
-	    
 	    unsafe {
 		    core::LLVMBuildICmp(self.into(), 
-	    		                  p, 
+	    		                  pred.to_int(signed), 
 	     		                  l.into(), r.into(), 
 	     		                  NULL_NAME.as_ptr())
 	    }.into()
 	     
     } else if lhs_ty.is_float() {
-    	let p = match pred {
-        Predicate::Equal => LLVMRealPredicate::LLVMRealOEQ,
-        Predicate::NotEqual => LLVMRealPredicate::LLVMRealONE,
-        Predicate::GreaterThan => LLVMRealPredicate::LLVMRealOGT,
-        Predicate::GreaterThanOrEqual => LLVMRealPredicate::LLVMRealOGE,
-        Predicate::LessThan => LLVMRealPredicate::LLVMRealOLT,
-        Predicate::LessThanOrEqual => LLVMRealPredicate::LLVMRealOLE
-      };
-    	
    	  unsafe { 
       	core::LLVMBuildFCmp(self.into(), 
-      		                 p, l.into(), r.into(), 
+      		                 pred.to_real(), l.into(), r.into(), 
       		                 NULL_NAME.as_ptr()) 
       }.into()
     	 
@@ -331,6 +368,7 @@ impl DisposeRef for Builder {
   type RefTo = LLVMBuilder;
   #[inline(always)]
   unsafe fn dispose(ptr: LLVMBuilderRef) {
-      core::LLVMDisposeBuilder(ptr)
+    VERIFYING.with(|verifying| verifying.borrow_mut().remove(&ptr));
+    core::LLVMDisposeBuilder(ptr)
   }
 }
@@ -0,0 +1,304 @@
+use std::cmp;
+use std::collections::HashSet;
+
+use ffi::core;
+use ffi::prelude::LLVMValueRef;
+use libc::c_uint;
+
+use instr::CallInst;
+use module::Module;
+use util::CastFrom;
+use value::{Function, Value};
+
+
+/// The functions of a module and which of them call each other.
+///
+/// Calls through a pointer can go to any function whose address is taken, which is any
+/// function that is used for something other than being called directly.
+///
+/// This is a snapshot of the module when it was built, so it must be built again after
+/// calls are added or removed.
+pub struct CallGraph<'a>
+{
+  functions: Vec<&'a Function>,
+  callees: Vec<Vec<usize>>,
+  indirect: Vec<bool>,
+  address_taken: Vec<bool>
+}
+
+impl<'a> CallGraph<'a>
+{
+  /// Build the call graph of `module`.
+  pub fn new(module: &'a Module) -> CallGraph<'a>
+  {
+    let functions: Vec<&'a Function> = module.into_iter().collect();
+    let mut callees = vec![Vec::new(); functions.len()];
+    let mut indirect = vec![false; functions.len()];
+    let mut address_taken = vec![false; functions.len()];
+    let index_of = |value: &Value| functions.iter().position(|&func| &**func == value);
+
+    for (i, func) in functions.iter().enumerate() {
+      for block in func.basic_blocks() {
+        for instr in block.instructions() {
+          if let Some(call) = CallInst::cast(instr) {
+            match call.get_called_function().and_then(|callee| index_of(&**callee)) {
+              Some(callee) => if !callees[i].contains(&callee) {
+                callees[i].push(callee)
+              },
+              None => indirect[i] = true
+            }
+          }
+        }
+      }
+
+      for usage in func.uses() {
+        let user = usage.get_user();
+        // The callee is the last operand of a call.
+        let is_callee = CallInst::cast(user).map(|call| {
+          let last = call.num_operands() - 1;
+          call.get_operand(last).map(|callee| callee == &***func).unwrap_or(false)
+        });
+        if is_callee != Some(true) {
+          address_taken[i] = true;
+        }
+      }
+    }
+
+    CallGraph {
+      functions: functions,
+      callees: callees,
+      indirect: indirect,
+      address_taken: address_taken
+    }
+  }
+
+  fn index(&self, func: &Function) -> usize
+  {
+    self.functions.iter()
+      .position(|&other| other == func)
+      .expect("function is not in this call graph")
+  }
+
+  fn to_functions(&self, indices: &[usize]) -> Vec<&'a Function>
+  {
+    indices.iter().map(|&i| self.functions[i]).collect()
+  }
+
+  /// Returns every function in the module.
+  pub fn functions(&self) -> &[&'a Function]
+  {
+    &self.functions
+  }
+
+  /// Returns the functions that `func` calls directly.
+  pub fn callees(&self, func: &Function) -> Vec<&'a Function>
+  {
+    self.to_functions(&self.callees[self.index(func)])
+  }
+
+  /// Returns the functions that call `func` directly.
+  pub fn callers(&self, func: &Function) -> Vec<&'a Function>
+  {
+    let index = self.index(func);
+    let callers: Vec<usize> = (0..self.functions.len())
+      .filter(|&i| self.callees[i].contains(&index))
+      .collect();
+    self.to_functions(&callers)
+  }
+
+  /// Returns true if `func` calls a function through a pointer.
+  pub fn has_indirect_calls(&self, func: &Function) -> bool
+  {
+    self.indirect[self.index(func)]
+  }
+
+  /// Returns true if `func` is used other than by being called directly, so it could be
+  /// called through a pointer.
+  pub fn is_address_taken(&self, func: &Function) -> bool
+  {
+    self.address_taken[self.index(func)]
+  }
+
+  /// Returns the functions that can be called, directly or indirectly, starting from the
+  /// `roots`, including the `roots` themselves.
+  pub fn reachable(&self, roots: &[&Function]) -> Vec<&'a Function>
+  {
+    let mut reached = vec![false; self.functions.len()];
+    let mut stack: Vec<usize> = roots.iter().map(|&root| self.index(root)).collect();
+    let mut indirect = false;
+    while let Some(i) = stack.pop() {
+      if reached[i] {
+        continue
+      }
+      reached[i] = true;
+      stack.extend(self.callees[i].iter().cloned());
+      if self.indirect[i] && !indirect {
+        indirect = true;
+        stack.extend((0..self.functions.len()).filter(|&j| self.address_taken[j]));
+      }
+    }
+    let reached: Vec<usize> = (0..self.functions.len()).filter(|&i| reached[i]).collect();
+    self.to_functions(&reached)
+  }
+
+  /// Returns the strongly connected components of the direct calls, which are the sets of
+  /// mutually recursive functions.
+  ///
+  /// A component comes before every component that calls into it, so callees can be
+  /// visited before their callers.
+  pub fn sccs(&self) -> Vec<Vec<&'a Function>>
+  {
+    // Tarjan's algorithm, without recursion.
+    let num = self.functions.len();
+    let mut index = vec![None; num];
+    let mut low = vec![0; num];
+    let mut on_stack = vec![false; num];
+    let mut stack = Vec::new();
+    let mut next_index = 0;
+    let mut sccs = Vec::new();
+
+    for start in 0..num {
+      if index[start].is_some() {
+        continue
+      }
+      let mut work = vec![(start, 0)];
+      while let Some(&mut (node, ref mut next)) = work.last_mut() {
+        if *next == 0 && index[node].is_none() {
+          index[node] = Some(next_index);
+          low[node] = next_index;
+          next_index += 1;
+          stack.push(node);
+          on_stack[node] = true;
+        }
+        if *next < self.callees[node].len() {
+          let callee = self.callees[node][*next];
+          *next += 1;
+          match index[callee] {
+            None => work.push((callee, 0)),
+            Some(callee_index) if on_stack[callee] => low[node] = cmp::min(low[node], callee_index),
+            Some(_) => ()
+          }
+        } else {
+          work.pop();
+          if let Some(&(parent, _)) = work.last() {
+            low[parent] = cmp::min(low[parent], low[node]);
+          }
+          if Some(low[node]) == index[node] {
+            let mut scc = Vec::new();
+            loop {
+              let member = stack.pop().unwrap();
+              on_stack[member] = false;
+              scc.push(member);
+              if member == node {
+                break
+              }
+            }
+            sccs.push(self.to_functions(&scc));
+          }
+        }
+      }
+    }
+    sccs
+  }
+}
+
+
+/// Add every global value that `value` refers to, looking through constants, to `refs`.
+fn collect_refs(value: LLVMValueRef, refs: &mut Vec<LLVMValueRef>, seen: &mut HashSet<LLVMValueRef>)
+{
+  unsafe {
+    for i in 0..core::LLVMGetNumOperands(value) {
+      let operand = core::LLVMGetOperand(value, i as c_uint);
+      if operand.is_null() || !seen.insert(operand) {
+        continue
+      }
+      if !core::LLVMIsAGlobalValue(operand).is_null() {
+        refs.push(operand);
+      } else if !core::LLVMIsAConstant(operand).is_null() {
+        collect_refs(operand, refs, seen);
+      }
+    }
+  }
+}
+
+impl Module
+{
+  /// Delete every function and global that can't be reached from the globals named in
+  /// `roots`, returning how many were deleted.
+  ///
+  /// A global is reached when a reached function or global initializer refers to it,
+  /// which includes calling it and taking its address. Globals whose names start with
+  /// `llvm.`, such as `llvm.global_ctors`, are always kept. So are aliases and ifuncs, along
+  /// with everything they refer to, since LLVM's C API has no way to
+  /// delete an alias.
+  ///
+  /// This is useful for shrinking a module to what is needed before compiling it.
+  pub fn retain_reachable(&self, roots: &[&str]) -> usize
+  {
+    let functions: Vec<LLVMValueRef> = self.into_iter().map(|func| func.into()).collect();
+    let globals: Vec<LLVMValueRef> = self.global_values().map(|global| global.into()).collect();
+    let mut aliases = Vec::new();
+    unsafe {
+      let mut alias = core::LLVMGetFirstGlobalAlias(self.into());
+      while !alias.is_null() {
+        aliases.push(alias);
+        alias = core::LLVMGetNextGlobalAlias(alias);
+      }
+      let mut ifunc = core::LLVMGetFirstGlobalIFunc(self.into());
+      while !ifunc.is_null() {
+        aliases.push(ifunc);
+        ifunc = core::LLVMGetNextGlobalIFunc(ifunc);
+      }
+    }
+    let is_root = |value: LLVMValueRef| {
+      let value: &Value = value.into();
+      let name = value.get_name().unwrap_or("");
+      name.starts_with("llvm.") || roots.iter().any(|&root| root == name)
+    };
+
+    let mut reached = HashSet::new();
+    let mut stack: Vec<LLVMValueRef> = functions.iter().chain(globals.iter())
+      .cloned()
+      .filter(|&value| is_root(value))
+      .chain(aliases)
+      .collect();
+    while let Some(value) = stack.pop() {
+      if !reached.insert(value) {
+        continue
+      }
+      let mut refs = Vec::new();
+      let mut seen = HashSet::new();
+      if unsafe { !core::LLVMIsAFunction(value).is_null() } {
+        let func: &Function = value.into();
+        for block in func.basic_blocks() {
+          for instr in block.instructions() {
+            collect_refs(instr.into(), &mut refs, &mut seen);
+          }
+        }
+      } else {
+        collect_refs(value, &mut refs, &mut seen);
+      }
+      stack.extend(refs);
+    }
+
+    // Anything still using a dead global is dead too, so replacing every use with undef
+    // lets each be deleted on its own.
+    let mut deleted = 0;
+    for &value in functions.iter().chain(globals.iter()) {
+      if reached.contains(&value) {
+        continue
+      }
+      unsafe {
+        let undef = core::LLVMGetUndef(core::LLVMTypeOf(value));
+        core::LLVMReplaceAllUsesWith(value, undef);
+        if !core::LLVMIsAFunction(value).is_null() {
+          core::LLVMDeleteFunction(value);
+        } else {
+          core::LLVMDeleteGlobal(value);
+        }
+      }
+      deleted += 1;
+    }
+    deleted
+  }
+}
@@ -1,13 +1,13 @@
 use std::mem;
 use std::ffi::CStr;
 
-use ffi::core;
-use ffi::prelude::LLVMValueRef;
+use ffi::{core, LLVMTypeKind};
 use libc::{c_char, c_uint, c_ulonglong};
 
-use context::Context;
+use context::{Context, GetContext};
 use value::Value;
 use ty::{StructType, Type};
+use util::CastFrom;
 
 
 /// A type that can be represented as a constant in LLVM IR.
@@ -188,6 +188,11 @@ impl<'a, 'b> Compile<'a> for &'b str
 }
 
 
+/// Byte slices compile into the same `{ [N x i8], usize }` string constant as `&str`.
+///
+/// Slices of other element types have no `Compile` implementation, because the type of
+/// the array they would compile into depends on their length. Use
+/// `Module::add_global_array` to embed one as a constant global such as a lookup table.
 impl<'a, 'b> Compile<'a> for &'b [u8] 
 {
   fn compile(self, context: &'a Context) -> &'a Value 
@@ -249,29 +254,47 @@ compile_tuple!{A = a, B = b, C = c, D = d, E = e, F = f}
 compile_tuple!{A = a, B = b, C = c, D = d, E = e, F = f, G = g}
 
 
-macro_rules! compile_array(
-  ($ty:ty, $num:expr) => (
-    impl<'a, T> Compile<'a> for $ty where T: Copy + Compile<'a> + 'a {
-      fn compile(self, context: &'a Context) -> &'a Value {
-        let values:Vec<_> = self.iter().map(|&value| value.compile(context)).collect();
-        unsafe { core::LLVMConstVector(values.as_ptr() as *mut LLVMValueRef, $num) }.into()
-      }
-      
-      fn get_type(context: &'a Context) -> &'a Type {
-        Type::vector_ty(Type::get::<T>(context), $num)
-      }
-    }
-  )
-);
+/// A fixed-size array that should be represented as an LLVM SIMD vector
+/// rather than an LLVM array.
+///
+/// ```rust
+/// use llvm::*;
+/// let ctx = Context::new();
+/// let vec = Simd([1u32, 2, 3, 4]).compile(&ctx);
+/// assert_eq!("<4 x i32>", format!("{}", vec.get_type()));
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Simd<T>(pub T);
+
+
+impl<'a, T, const N: usize> Compile<'a> for [T; N] where T: Copy + Compile<'a> + 'a 
+{
+  fn compile(self, context: &'a Context) -> &'a Value 
+  {
+    let values:Vec<_> = self.iter().map(|&value| value.compile(context)).collect();
+    Value::new_array(T::get_type(context), &values)
+  }
+  
+  fn get_type(context: &'a Context) -> &'a Type 
+  {
+    Type::array_ty(Type::get::<T>(context), N)
+  }
+}
 
 
-compile_array!{[T; 0], 0}
-compile_array!{[T; 1], 1}
-compile_array!{[T; 2], 2}
-compile_array!{[T; 3], 3}
-compile_array!{[T; 4], 4}
-compile_array!{[T; 5], 5}
-compile_array!{[T; 6], 6}
+impl<'a, T, const N: usize> Compile<'a> for Simd<[T; N]> where T: Copy + Compile<'a> + 'a 
+{
+  fn compile(self, context: &'a Context) -> &'a Value 
+  {
+    let values:Vec<_> = self.0.iter().map(|&value| value.compile(context)).collect();
+    Value::new_vector(&values)
+  }
+  
+  fn get_type(context: &'a Context) -> &'a Type 
+  {
+    Type::vector_ty(Type::get::<T>(context), N)
+  }
+}
 
 
 macro_rules! compile_func(
@@ -321,3 +344,161 @@ compile_func!{A, B, C, D}
 compile_func!{A, B, C, D, E}
 compile_func!{A, B, C, D, E, F}
 compile_func!{A, B, C, D, E, F, G}
+
+
+/// A type that can be read back from a constant in LLVM IR.
+///
+/// This is the reverse of `Compile`, so that folded constants can be inspected from Rust.
+///
+/// ```rust
+/// use llvm::*;
+/// let ctx = Context::new();
+/// let value = (42u32, true).compile(&ctx);
+/// assert_eq!(Some((42u32, true)), <(u32, bool)>::decompile(value));
+/// ```
+pub trait Decompile<'a> : Sized {
+  /// Read a value of this type out of the constant given, or `None` if it isn't
+  /// a constant of the matching type.
+  fn decompile(value: &'a Value) -> Option<Self>;
+}
+
+/// Returns the constant integer `value` is, if it is one of `ty`.
+fn get_const_int<'a>(value: &'a Value, ty: &'a Type) -> Option<&'a Value> 
+{
+  let is_int = unsafe { !core::LLVMIsAConstantInt(value.into()).is_null() };
+  if is_int && value.get_type() == ty {
+    Some(value)
+  } else {
+    None
+  }
+}
+
+/// Returns the element at `index` of the constant aggregate `value`.
+fn get_const_element<'a>(value: &'a Value, index: usize) -> Option<&'a Value> 
+{
+  unsafe {
+    let ptr = value.into();
+    if !core::LLVMIsAConstantAggregateZero(ptr).is_null() {
+      let ty = StructType::cast(value.get_type()).map(|ty| ty.get_elements());
+      ty.and_then(|elems| elems.get(index).map(|&elem| Value::null(elem)))
+    } else if !core::LLVMIsAConstantStruct(ptr).is_null() 
+        && index < core::LLVMGetNumOperands(ptr) as usize {
+      Some(core::LLVMGetOperand(ptr, index as c_uint).into())
+    } else {
+      None
+    }
+  }
+}
+
+macro_rules! decompile_int(
+  ($ty:ty, $func:ident) => (
+    impl<'a> Decompile<'a> for $ty {
+      fn decompile(value: &'a Value) -> Option<$ty> 
+      {
+        let ty = <$ty as Compile<'a>>::get_type(value.get_context());
+        get_const_int(value, ty).map(|value| unsafe { core::$func(value.into()) as $ty })
+      }
+    }
+  );
+);
+
+decompile_int!{i8, LLVMConstIntGetSExtValue}
+decompile_int!{u8, LLVMConstIntGetZExtValue}
+decompile_int!{i16, LLVMConstIntGetSExtValue}
+decompile_int!{u16, LLVMConstIntGetZExtValue}
+decompile_int!{i32, LLVMConstIntGetSExtValue}
+decompile_int!{u32, LLVMConstIntGetZExtValue}
+decompile_int!{i64, LLVMConstIntGetSExtValue}
+decompile_int!{u64, LLVMConstIntGetZExtValue}
+decompile_int!{isize, LLVMConstIntGetSExtValue}
+decompile_int!{usize, LLVMConstIntGetZExtValue}
+
+impl<'a> Decompile<'a> for bool 
+{
+  fn decompile(value: &'a Value) -> Option<bool> 
+  {
+    let ty = <bool as Compile<'a>>::get_type(value.get_context());
+    get_const_int(value, ty).map(|value| unsafe { core::LLVMConstIntGetZExtValue(value.into()) != 0 })
+  }
+}
+
+impl<'a> Decompile<'a> for char 
+{
+  fn decompile(value: &'a Value) -> Option<char> 
+  {
+    u32::decompile(value).and_then(::std::char::from_u32)
+  }
+}
+
+macro_rules! decompile_float(
+  ($ty:ty) => (
+    impl<'a> Decompile<'a> for $ty {
+      fn decompile(value: &'a Value) -> Option<$ty> 
+      {
+        let ty = <$ty as Compile<'a>>::get_type(value.get_context());
+        unsafe {
+          if core::LLVMIsAConstantFP(value.into()).is_null() || value.get_type() != ty {
+            None
+          } else {
+            let mut loses_info = 0;
+            Some(core::LLVMConstRealGetDouble(value.into(), &mut loses_info) as $ty)
+          }
+        }
+      }
+    }
+  );
+);
+
+decompile_float!{f32}
+decompile_float!{f64}
+
+/// Strings are read back from the `{ [N x i8], usize }` constant that compiling them produces.
+impl<'a> Decompile<'a> for &'a str 
+{
+  fn decompile(value: &'a Value) -> Option<&'a str> 
+  {
+    let data = match get_const_element(value, 0) {
+      Some(data) => data,
+      None => return None
+    };
+    unsafe {
+      if core::LLVMIsConstantString(data.into()) == 0 {
+        // An empty string compiles into a zero-length array, which LLVM makes a
+        // `ConstantAggregateZero` rather than a constant string.
+        let ty = data.get_type().into();
+        let is_empty = core::LLVMGetTypeKind(ty) == LLVMTypeKind::LLVMArrayTypeKind
+          && core::LLVMGetArrayLength2(ty) == 0
+          && core::LLVMGetElementType(ty) == Type::get::<u8>(value.get_context()).into();
+        return if is_empty { Some("") } else { None }
+      }
+      let mut len = 0;
+      let ptr = core::LLVMGetAsString(data.into(), &mut len) as *const u8;
+      let bytes: &'a [u8] = ::std::slice::from_raw_parts(ptr, len as usize);
+      ::std::str::from_utf8(bytes).ok()
+    }
+  }
+}
+
+macro_rules! decompile_tuple(
+  ($($name:ident = $index:expr),+) => (
+    impl<'a, $($name),+> Decompile<'a> for ($($name),+) where $($name:Decompile<'a>),+ 
+    {
+      fn decompile(value: &'a Value) -> Option<($($name),+)> 
+      {
+        Some(($(
+          match get_const_element(value, $index).and_then($name::decompile) {
+            Some(elem) => elem,
+            None => return None
+          }
+        ),+))
+      }
+    }
+  )
+);
+
+decompile_tuple!{A = 0, B = 1}
+decompile_tuple!{A = 0, B = 1, C = 2}
+decompile_tuple!{A = 0, B = 1, C = 2, D = 3}
+decompile_tuple!{A = 0, B = 1, C = 2, D = 3, E = 4}
+decompile_tuple!{A = 0, B = 1, C = 2, D = 3, E = 4, F = 5}
+decompile_tuple!{A = 0, B = 1, C = 2, D = 3, E = 4, F = 5, G = 6}
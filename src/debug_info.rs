@@ -0,0 +1,391 @@
+use std::ops::Deref;
+use std::{mem, ptr};
+
+use ffi::core;
+use ffi::debuginfo::*;
+use ffi::prelude::{LLVMDIBuilderRef, LLVMMetadataRef};
+use ffi::LLVMModuleFlagBehavior;
+use libc::{c_char, c_uint};
+
+use block::BasicBlock;
+use builder::Builder;
+use context::GetContext;
+use module::Module;
+use value::{Function, Value};
+
+
+/// A debug info descriptor for something that can contain other descriptors, such as a
+/// compile unit, function or lexical block.
+pub struct DIScope;
+native_ref!(&DIScope = LLVMMetadataRef);
+
+/// A debug info descriptor for a source file.
+pub struct DIFile;
+native_ref!(&DIFile = LLVMMetadataRef);
+deref!(DIFile, DIScope);
+
+/// A debug info descriptor for a type.
+pub struct DIType;
+native_ref!(&DIType = LLVMMetadataRef);
+
+/// A debug info descriptor for a local variable or parameter.
+pub struct DIVariable;
+native_ref!(&DIVariable = LLVMMetadataRef);
+
+/// How the bits of a basic type are interpreted by a debugger.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Encoding
+{
+  /// A pointer-sized address.
+  Address,
+  /// A boolean.
+  Boolean,
+  /// A floating-point number.
+  Float,
+  /// A signed integer.
+  Signed,
+  /// An unsigned integer.
+  Unsigned
+}
+
+impl From<Encoding> for LLVMDWARFTypeEncoding
+{
+  fn from(encoding: Encoding) -> LLVMDWARFTypeEncoding
+  {
+    // The `DW_ATE_*` constants from the DWARF standard.
+    match encoding {
+      Encoding::Address => 0x01,
+      Encoding::Boolean => 0x02,
+      Encoding::Float => 0x04,
+      Encoding::Signed => 0x05,
+      Encoding::Unsigned => 0x08
+    }
+  }
+}
+
+
+/// The language a compile unit was written in, which tells a debugger how to show it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Language
+{
+  /// C, with no particular standard.
+  C,
+  /// C89.
+  C89,
+  /// C99.
+  C99,
+  /// C11.
+  C11,
+  /// C17.
+  C17,
+  /// C++, with no particular standard.
+  CPlusPlus,
+  /// C++11.
+  CPlusPlus11,
+  /// C++14.
+  CPlusPlus14,
+  /// C++17.
+  CPlusPlus17,
+  /// C++20.
+  CPlusPlus20,
+  /// Objective-C.
+  ObjC,
+  /// Assembly.
+  Assembly,
+  /// D.
+  D,
+  /// Go.
+  Go,
+  /// Haskell.
+  Haskell,
+  /// Julia.
+  Julia,
+  /// OCaml.
+  OCaml,
+  /// Rust.
+  Rust,
+  /// Swift.
+  Swift,
+  /// Zig.
+  Zig
+}
+
+impl From<Language> for LLVMDWARFSourceLanguage
+{
+  fn from(language: Language) -> LLVMDWARFSourceLanguage
+  {
+    use ffi::debuginfo::LLVMDWARFSourceLanguage::*;
+    match language {
+      Language::C => LLVMDWARFSourceLanguageC,
+      Language::C89 => LLVMDWARFSourceLanguageC89,
+      Language::C99 => LLVMDWARFSourceLanguageC99,
+      Language::C11 => LLVMDWARFSourceLanguageC11,
+      Language::C17 => LLVMDWARFSourceLanguageC17,
+      Language::CPlusPlus => LLVMDWARFSourceLanguageC_plus_plus,
+      Language::CPlusPlus11 => LLVMDWARFSourceLanguageC_plus_plus_11,
+      Language::CPlusPlus14 => LLVMDWARFSourceLanguageC_plus_plus_14,
+      Language::CPlusPlus17 => LLVMDWARFSourceLanguageC_plus_plus_17,
+      Language::CPlusPlus20 => LLVMDWARFSourceLanguageC_plus_plus_20,
+      Language::ObjC => LLVMDWARFSourceLanguageObjC,
+      Language::Assembly => LLVMDWARFSourceLanguageAssembly,
+      Language::D => LLVMDWARFSourceLanguageD,
+      Language::Go => LLVMDWARFSourceLanguageGo,
+      Language::Haskell => LLVMDWARFSourceLanguageHaskell,
+      Language::Julia => LLVMDWARFSourceLanguageJulia,
+      Language::OCaml => LLVMDWARFSourceLanguageOCaml,
+      Language::Rust => LLVMDWARFSourceLanguageRust,
+      Language::Swift => LLVMDWARFSourceLanguageSwift,
+      Language::Zig => LLVMDWARFSourceLanguageZig
+    }
+  }
+}
+
+/// Creates the DWARF debug info for a module, so debuggers and profilers can map the
+/// machine code compiled from it back to the source it was generated from.
+///
+/// This must be finalized, either with `finalize` or by dropping it, before the module is
+/// verified or compiled.
+pub struct DebugInfoBuilder<'a>
+{
+  builder: LLVMDIBuilderRef,
+  module: &'a Module,
+  finalized: bool
+}
+
+impl<'a> DebugInfoBuilder<'a>
+{
+  /// Create a new debug info builder for `module`, which will emit DWARF of the version
+  /// given.
+  ///
+  /// This adds the module flags LLVM needs to emit debug info.
+  pub fn new(module: &'a Module, dwarf_version: u32) -> DebugInfoBuilder<'a>
+  {
+    unsafe {
+      let debug_version = LLVMDebugMetadataVersion();
+      add_flag(module, "Dwarf Version", dwarf_version);
+      add_flag(module, "Debug Info Version", debug_version);
+      DebugInfoBuilder {
+        builder: LLVMCreateDIBuilder(module.into()),
+        module: module,
+        finalized: false
+      }
+    }
+  }
+
+  /// Create a descriptor for the file with the name given in `directory`.
+  pub fn create_file(&self, name: &str, directory: &str) -> &'a DIFile
+  {
+    unsafe {
+      LLVMDIBuilderCreateFile(self.builder,
+                              name.as_ptr() as *const c_char, name.len(),
+                              directory.as_ptr() as *const c_char, directory.len()).into()
+    }
+  }
+
+  /// Create the compile unit for the source in `file`, which is written in `language` and
+  /// was produced by the tool named `producer`.
+  ///
+  /// There should be exactly one compile unit per module, and it is the outermost scope.
+  pub fn create_compile_unit(&self, file: &DIFile, language: Language, producer: &str, optimized: bool) -> &'a DIScope
+  {
+    unsafe {
+      LLVMDIBuilderCreateCompileUnit(self.builder,
+                                     language.into(),
+                                     file.into(),
+                                     producer.as_ptr() as *const c_char, producer.len(),
+                                     optimized as i32,
+                                     ptr::null(), 0,
+                                     0,
+                                     ptr::null(), 0,
+                                     LLVMDWARFEmissionKind::LLVMDWARFEmissionKindFull,
+                                     0, 0, 0,
+                                     ptr::null(), 0,
+                                     ptr::null(), 0).into()
+    }
+  }
+
+  /// Create a descriptor for `func`, which was written at `line` in `file` with the
+  /// signature `ty`, and attach it to `func`.
+  ///
+  /// This is the scope of everything inside the function.
+  pub fn create_function(&self, scope: &DIScope, func: &Function, file: &DIFile, line: u32, ty: &DIType) -> &'a DIScope
+  {
+    let name = func.get_name();
+    unsafe {
+      let subprogram = LLVMDIBuilderCreateFunction(self.builder, scope.into(),
+                                                   name.as_ptr() as *const c_char, name.len(),
+                                                   name.as_ptr() as *const c_char, name.len(),
+                                                   file.into(), line as c_uint, ty.into(),
+                                                   0, 1, line as c_uint,
+                                                   LLVMDIFlagZero, 0);
+      LLVMSetSubprogram(func.into(), subprogram);
+      subprogram.into()
+    }
+  }
+
+  /// Create a descriptor for a block of code starting at `line` and `column` in `file`,
+  /// which can contain its own variables.
+  pub fn create_lexical_block(&self, scope: &DIScope, file: &DIFile, line: u32, column: u32) -> &'a DIScope
+  {
+    unsafe {
+      LLVMDIBuilderCreateLexicalBlock(self.builder, scope.into(), file.into(),
+                                      line as c_uint, column as c_uint).into()
+    }
+  }
+
+  /// Create a descriptor for a type with the name given that is `size` bits and has its
+  /// bits interpreted with `encoding`.
+  pub fn create_basic_type(&self, name: &str, size: u64, encoding: Encoding) -> &'a DIType
+  {
+    unsafe {
+      LLVMDIBuilderCreateBasicType(self.builder, name.as_ptr() as *const c_char, name.len(),
+                                   size, encoding.into(), LLVMDIFlagZero).into()
+    }
+  }
+
+  /// Create a descriptor for a pointer to `pointee` that is `size` bits.
+  pub fn create_pointer_type(&self, pointee: &DIType, size: u64) -> &'a DIType
+  {
+    unsafe {
+      LLVMDIBuilderCreatePointerType(self.builder, pointee.into(), size, 0, 0, ptr::null(), 0).into()
+    }
+  }
+
+  /// Create a descriptor for the signature of a function that returns `ret`, or nothing if
+  /// it is `None`, and takes `params`.
+  pub fn create_function_type(&self, file: &DIFile, ret: Option<&DIType>, params: &[&DIType]) -> &'a DIType
+  {
+    let mut types: Vec<LLVMMetadataRef> = vec![ret.map(|ty| ty.into()).unwrap_or(ptr::null_mut())];
+    types.extend(params.iter().map(|&ty| -> LLVMMetadataRef { ty.into() }));
+    unsafe {
+      LLVMDIBuilderCreateSubroutineType(self.builder, file.into(),
+                                        types.as_mut_ptr(), types.len() as c_uint,
+                                        LLVMDIFlagZero).into()
+    }
+  }
+
+  /// Create a descriptor for a member of a struct, with the name and type given, that is
+  /// `offset` bits from the start of the struct.
+  pub fn create_member_type(&self, scope: &DIScope, name: &str, file: &DIFile, line: u32,
+                            ty: &DIType, size: u64, align: u32, offset: u64) -> &'a DIType
+  {
+    unsafe {
+      LLVMDIBuilderCreateMemberType(self.builder, scope.into(),
+                                    name.as_ptr() as *const c_char, name.len(),
+                                    file.into(), line as c_uint,
+                                    size, align, offset, LLVMDIFlagZero, ty.into()).into()
+    }
+  }
+
+  /// Create a descriptor for a struct with the name given, made of `members`, which should
+  /// be created with `create_member_type`.
+  pub fn create_struct_type(&self, scope: &DIScope, name: &str, file: &DIFile, line: u32,
+                            size: u64, align: u32, members: &[&DIType]) -> &'a DIType
+  {
+    let mut elements: Vec<LLVMMetadataRef> = members.iter().map(|&ty| ty.into()).collect();
+    unsafe {
+      LLVMDIBuilderCreateStructType(self.builder, scope.into(),
+                                    name.as_ptr() as *const c_char, name.len(),
+                                    file.into(), line as c_uint, size, align, LLVMDIFlagZero,
+                                    ptr::null_mut(),
+                                    elements.as_mut_ptr(), elements.len() as c_uint,
+                                    0, ptr::null_mut(), ptr::null(), 0).into()
+    }
+  }
+
+  /// Create a descriptor for a local variable with the name and type given, declared at
+  /// `line` in `file`.
+  pub fn create_local_variable(&self, scope: &DIScope, name: &str, file: &DIFile, line: u32, ty: &DIType) -> &'a DIVariable
+  {
+    unsafe {
+      LLVMDIBuilderCreateAutoVariable(self.builder, scope.into(),
+                                      name.as_ptr() as *const c_char, name.len(),
+                                      file.into(), line as c_uint, ty.into(),
+                                      1, LLVMDIFlagZero, 0).into()
+    }
+  }
+
+  /// Create a descriptor for the parameter at `index` of a function, counting from 1,
+  /// with the name and type given.
+  pub fn create_parameter_variable(&self, scope: &DIScope, name: &str, index: u32, file: &DIFile, line: u32, ty: &DIType) -> &'a DIVariable
+  {
+    unsafe {
+      LLVMDIBuilderCreateParameterVariable(self.builder, scope.into(),
+                                           name.as_ptr() as *const c_char, name.len(),
+                                           index as c_uint, file.into(), line as c_uint, ty.into(),
+                                           1, LLVMDIFlagZero).into()
+    }
+  }
+
+  /// Declare that `var` is stored at `storage`, which is usually an `alloca`, from the
+  /// end of `block` onwards, at `line` and `column` in `scope`.
+  pub fn declare(&self, storage: &Value, var: &DIVariable, block: &BasicBlock, line: u32, column: u32, scope: &DIScope)
+  {
+    unsafe {
+      let context = self.module.get_context();
+      let location = LLVMDIBuilderCreateDebugLocation(context.into(), line as c_uint, column as c_uint,
+                                                      scope.into(), ptr::null_mut());
+      let expr = LLVMDIBuilderCreateExpression(self.builder, ptr::null_mut(), 0);
+      LLVMDIBuilderInsertDeclareRecordAtEnd(self.builder, storage.into(), var.into(), expr,
+                                            location, block.into());
+    }
+  }
+
+  /// Create any descriptors that were deferred until everything was added.
+  ///
+  /// Nothing should be added after this, and this is done when the builder is dropped if
+  /// it wasn't done before.
+  pub fn finalize(&mut self)
+  {
+    if !self.finalized {
+      unsafe { LLVMDIBuilderFinalize(self.builder) }
+      self.finalized = true;
+    }
+  }
+}
+
+impl<'a> Drop for DebugInfoBuilder<'a>
+{
+  fn drop(&mut self)
+  {
+    self.finalize();
+    unsafe { LLVMDisposeDIBuilder(self.builder) }
+  }
+}
+
+/// Add the module flag `key` with the integer `value` to `module`.
+unsafe fn add_flag(module: &Module, key: &str, value: u32)
+{
+  let context = module.get_context();
+  let value = core::LLVMConstInt(core::LLVMInt32TypeInContext(context.into()), value as u64, 0);
+  core::LLVMAddModuleFlag(module.into(),
+                          LLVMModuleFlagBehavior::LLVMModuleFlagBehaviorWarning,
+                          key.as_ptr() as *const c_char, key.len(),
+                          core::LLVMValueAsMetadata(value));
+}
+
+
+impl Builder
+{
+  /// Attach the source location at `line` and `column` in `scope` to every instruction
+  /// built after this, until it is changed or cleared.
+  ///
+  /// The builder must be positioned in a block first, and this panics if it isn't.
+  pub fn set_current_debug_location(&self, line: u32, column: u32, scope: &DIScope)
+  {
+    let block = unsafe { core::LLVMGetInsertBlock(self.into()) };
+    assert!(!block.is_null(), "the builder must be positioned in a block to set a debug location");
+    let context = self.get_insert_block().as_value().get_context();
+    unsafe {
+      let location = LLVMDIBuilderCreateDebugLocation(context.into(), line as c_uint, column as c_uint,
+                                                      scope.into(), ptr::null_mut());
+      core::LLVMSetCurrentDebugLocation2(self.into(), location);
+    }
+  }
+
+  /// Stop attaching a source location to the instructions built after this.
+  pub fn clear_current_debug_location(&self)
+  {
+    unsafe { core::LLVMSetCurrentDebugLocation2(self.into(), ptr::null_mut()) }
+  }
+}
@@ -0,0 +1,117 @@
+use std::io::{self, Write};
+
+use block::BasicBlock;
+use call_graph::CallGraph;
+use instr::{BranchInst, Instruction, SwitchInst};
+use module::Module;
+use util::CastFrom;
+use value::Function;
+
+
+/// Escape `text` so it can be put in a quoted DOT string.
+fn escape(text: &str) -> String
+{
+  let mut escaped = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '"' | '\\' => {
+        escaped.push('\\');
+        escaped.push(c);
+      },
+      '\n' => escaped.push_str("\\l"),
+      _ => escaped.push(c)
+    }
+  }
+  escaped
+}
+
+/// Returns the label of the edge from `term` to its successor at `index`.
+fn edge_label(term: &Instruction, index: usize) -> Option<String>
+{
+  if let Some(br) = BranchInst::cast(term) {
+    if br.is_conditional() {
+      return Some(if index == 0 { "true" } else { "false" }.to_owned())
+    }
+  } else if SwitchInst::cast(term).is_some() {
+    // The operands of a switch are its condition and default, then pairs of each case's
+    // value and destination.
+    return Some(match term.get_operand(index * 2) {
+      Some(value) if index > 0 => format!("{}", value).trim().to_owned(),
+      _ => "default".to_owned()
+    })
+  }
+  None
+}
+
+impl Function
+{
+  /// Write the control flow graph of this function in the Graphviz DOT format.
+  ///
+  /// Each basic block is a node listing its instructions, and each edge is labelled with
+  /// the branch condition or switch case it is taken for.
+  pub fn to_dot<W>(&self, out: &mut W) -> io::Result<()> where W: Write
+  {
+    let blocks: Vec<&BasicBlock> = self.basic_blocks().collect();
+    let node = |block: &BasicBlock| blocks.iter().position(|&other| other == block);
+
+    try!(writeln!(out, "digraph \"{}\" {{", escape(self.get_name())));
+    try!(writeln!(out, "  node [shape=box, fontname=monospace];"));
+    for (i, block) in blocks.iter().enumerate() {
+      let name = block.get_name().map(|name| name.to_owned()).unwrap_or(format!("bb{}", i));
+      let mut label = format!("{}:\\l", escape(&name));
+      for instr in block.instructions() {
+        label.push_str(&escape(format!("{}", instr).trim()));
+        label.push_str("\\l");
+      }
+      try!(writeln!(out, "  bb{} [label=\"{}\"];", i, label));
+    }
+    for (i, block) in blocks.iter().enumerate() {
+      let term = match block.get_terminator() {
+        Some(term) => term,
+        None => continue
+      };
+      for index in 0..term.num_successors() {
+        let succ = node(term.get_successor(index).unwrap()).unwrap();
+        match edge_label(term, index) {
+          Some(label) => try!(writeln!(out, "  bb{} -> bb{} [label=\"{}\"];", i, succ, escape(&label))),
+          None => try!(writeln!(out, "  bb{} -> bb{};", i, succ))
+        }
+      }
+    }
+    writeln!(out, "}}")
+  }
+}
+
+impl Module
+{
+  /// Write the call graph of this module in the Graphviz DOT format.
+  ///
+  /// Each function is a node, with declarations drawn dashed, and there is an edge for
+  /// each function that calls another directly. Calls through a pointer go to a separate
+  /// node for indirect calls.
+  pub fn call_graph_dot<W>(&self, out: &mut W) -> io::Result<()> where W: Write
+  {
+    let graph = CallGraph::new(self);
+    let funcs = graph.functions();
+    let node = |func: &Function| funcs.iter().position(|&other| other == func).unwrap();
+
+    try!(writeln!(out, "digraph callgraph {{"));
+    try!(writeln!(out, "  node [shape=box];"));
+    for (i, func) in funcs.iter().enumerate() {
+      let style = if func.get_entry().is_none() { ", style=dashed" } else { "" };
+      try!(writeln!(out, "  f{} [label=\"{}\"{}];", i, escape(func.get_name()), style));
+    }
+    if funcs.iter().any(|&func| graph.has_indirect_calls(func)) {
+      try!(writeln!(out, "  indirect [label=\"<indirect>\", shape=ellipse, style=dashed];"));
+    }
+    for (i, &func) in funcs.iter().enumerate() {
+      for callee in graph.callees(func) {
+        try!(writeln!(out, "  f{} -> f{};", i, node(callee)));
+      }
+      if graph.has_indirect_calls(func) {
+        try!(writeln!(out, "  f{} -> indirect [style=dashed];", i));
+      }
+    }
+    writeln!(out, "}}")
+  }
+}
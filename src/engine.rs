@@ -1,18 +1,28 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
 use std::marker::PhantomData;
 use std::mem;
 use std::ptr;
 use std::ops::*;
+use std::rc::Rc;
 
-use cbox::CBox;
-use ffi::{core, target};
+use cbox::{CBox, CSemiBox};
+use ffi::{core, target, LLVMTypeKind};
 use ffi::execution_engine as engine;
 use ffi::execution_engine::*;
+use ffi::prelude::{LLVMModuleRef, LLVMTypeRef};
 use ffi::target_machine::LLVMCodeModel;
 use libc::{c_int, c_void, c_uint, c_ulonglong};
 
 use compile::Compile;
 use context::{Context, GetContext};
+use externals::Externals;
+use hot::Redefinable;
+use jit_function::JitFunction;
+use memory::{self, MemoryManager, SharedMemoryManager};
 use module::Module;
+use signature::{self, JitSignature, SignatureError};
 use ty::{StructType, Type};
 use util::{self, CastFrom};
 use value::{Function, Value};
@@ -20,46 +30,47 @@ use value::{Function, Value};
 /// An abstract interface for implementation execution of LLVM modules.
 ///
 /// This is designed to support both interpreter and just-in-time (JIT) compiler implementations.
-pub trait ExecutionEngine<'a, 'b:'a> where LLVMExecutionEngineRef:From<&'b Self> 
+///
+/// An engine owns every module it interprets or compiles, and deletes them when it is dropped.
+pub trait ExecutionEngine<'a>: Sized where for<'e> LLVMExecutionEngineRef: From<&'e Self> 
 {
   /// The options given to the engine upon creation.
-  type Options : Copy;
+  type Options : Clone;
   
-  /// Create a new execution engine with the given `Module` and options, or return a
-  /// description of the error.
-  fn new(module: &'a Module, options: Self::Options) -> Result<Self, CBox<str>>;
+  /// Create a new execution engine that owns the given `Module`, with the options given, or
+  /// return a description of the error.
+  fn new(module: CSemiBox<'a, Module>, options: Self::Options) -> Result<Self, CBox<str>>;
   
-  /// Add a module to the list of modules to interpret or compile.
-  fn add_module(&'b self, module: &'a Module) 
-  {
-      unsafe { engine::LLVMAddModule(self.into(), (&*module).into()) }
-  }
+  /// Add a module to the list of modules to interpret or compile, which this engine takes
+  /// ownership of, and return a handle to it.
+  fn add_module(&self, module: CSemiBox<'a, Module>) -> ModuleHandle;
   
-  /// Remove a module from the list of modules to interpret or compile.
-  fn remove_module(&'b self, module: &'a Module) -> &'a Module 
-  {
-      unsafe {
-          let mut out = mem::uninitialized();
-          engine::LLVMRemoveModule(self.into(), module.into(), &mut out, ptr::null_mut());
-          out.into()
-      }
-  }
+  /// Remove the module `handle` identifies from the list of modules to interpret or compile,
+  /// and give ownership of it back, or return a description of the error.
+  ///
+  /// This borrows the engine mutably, so it can't be done while a `JitFunction` from it
+  /// exists.
+  fn remove_module(&mut self, handle: ModuleHandle) -> Result<CSemiBox<'a, Module>, CBox<str>>;
+  
+  /// Returns the handle of the module `function` is in, or `None` if this engine doesn't
+  /// own it.
+  fn get_module_handle(&self, function: &Function) -> Option<ModuleHandle>;
   
   /// Execute all of the static constructors for this program.
-  fn run_static_constructors(&'b self) 
+  fn run_static_constructors(&self) 
   {
       unsafe { engine::LLVMRunStaticConstructors(self.into()) }
   }
   
   /// Execute all of the static destructors for this program.
-  fn run_static_destructors(&'b self) 
+  fn run_static_destructors(&self) 
   {
       unsafe { engine::LLVMRunStaticDestructors(self.into()) }
   }
   
   /// Attempt to find a function with the name given, or `None` if there wasn't
   /// a function with that name.
-  fn find_function(&'b self, name: &str) -> Option<&'a Function> 
+  fn find_function(&self, name: &str) -> Option<&Function> 
   {
       util::with_cstr(name, |c_name| unsafe {
           let mut out = mem::zeroed();
@@ -75,17 +86,31 @@ pub trait ExecutionEngine<'a, 'b:'a> where LLVMExecutionEngineRef:From<&'b Self>
   ///
   /// To convert the arguments to `GenericValue`s, you should use the `GenericValueCast::to_generic` method.
   /// To convert the return value from a `GenericValue`, you should use the `GenericValueCast::from_generic` method.
-  fn run_function(&'b self, function: &'a Function, args: &[GenericValue<'a>]) -> GenericValue<'a> 
+  fn run_function(&self, function: &Function, args: &[GenericValue<'a>]) -> GenericValue<'a> 
   {
-      let ptr = args.as_ptr() as *mut LLVMGenericValueRef;
-      unsafe { engine::LLVMRunFunction(self.into(), function.into(), args.len() as c_uint, ptr).into() }
+      let mut args: Vec<LLVMGenericValueRef> = args.iter().map(|arg| arg.into()).collect();
+      unsafe {
+          let result = engine::LLVMRunFunction(self.into(), function.into(), args.len() as c_uint, args.as_mut_ptr());
+          GenericValue::typed(result, function.get_signature().get_return())
+      }
+  }
+  
+  /// Run `function` like `run_function`, but first check that the arguments given match its
+  /// parameters, or return how they don't.
+  ///
+  /// A value made with `GenericValue::from_pointer` matches any pointer parameter, and a
+  /// value made from a native pointer matches any parameter.
+  fn run_function_checked(&self, function: &Function, args: &[GenericValue<'a>]) -> Result<GenericValue<'a>, SignatureError> 
+  {
+      try!(check_args(function, args));
+      Ok(self.run_function(function, args))
   }
   
   /// Returns a pointer to the global value given.
   ///
   /// This is marked as unsafe because the type cannot be guranteed to be the same as the
   /// type of the global value at this point.
-  unsafe fn get_global<T>(&'b self, global: &'a Value) -> &'b T 
+  unsafe fn get_global<T>(&self, global: &Value) -> &T 
   {
       mem::transmute(engine::LLVMGetPointerToGlobal(self.into(), global.into()))
   }
@@ -94,7 +119,7 @@ pub trait ExecutionEngine<'a, 'b:'a> where LLVMExecutionEngineRef:From<&'b Self>
   ///
   /// This is marked as unsafe because the type cannot be guranteed to be the same as the
   /// type of the global value at this point.
-  unsafe fn find_global<T>(&'b self, name: &str) -> Option<&'b T> 
+  unsafe fn find_global<T>(&self, name: &str) -> Option<&T> 
   {
       util::with_cstr(name, |ptr|
           mem::transmute(engine::LLVMGetGlobalValueAddress(self.into(), ptr))
@@ -102,21 +127,289 @@ pub trait ExecutionEngine<'a, 'b:'a> where LLVMExecutionEngineRef:From<&'b Self>
   }
   
   /// Maps a global to a specific memory location.
-  unsafe fn add_global_mapping<T>(&'b self, global: &'a Value, addr: *const T) 
+  unsafe fn add_global_mapping<T>(&self, global: &Value, addr: *const T) 
   {
     engine::LLVMAddGlobalMapping(self.into(), global.into(), mem::transmute(addr));
   }
 }
 
 
+/// Identifies a module that was added to an `ExecutionEngine`.
+///
+/// Handles are numbered by each engine in the order its modules are added, so a handle is
+/// never reused by the engine it came from, even after its module is removed.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ModuleHandle(usize);
+
+/// The modules an engine owns, with the handles they were given.
+pub(crate) struct OwnedModules
+{
+  modules: Vec<(ModuleHandle, LLVMModuleRef)>,
+  next: usize
+}
+
+impl OwnedModules
+{
+  pub(crate) fn new() -> OwnedModules
+  {
+    OwnedModules {
+      modules: Vec::new(),
+      next: 0
+    }
+  }
+
+  /// Record `module`, which the engine has taken ownership of, and return its new handle.
+  pub(crate) fn push(&mut self, module: LLVMModuleRef) -> ModuleHandle
+  {
+    let handle = ModuleHandle(self.next);
+    self.next += 1;
+    self.modules.push((handle, module));
+    handle
+  }
+
+  /// Forget the module `handle` identifies and return it, or `None` if it isn't recorded.
+  pub(crate) fn remove(&mut self, handle: ModuleHandle) -> Option<LLVMModuleRef>
+  {
+    let index = match self.modules.iter().position(|&(other, _)| other == handle) {
+      Some(index) => index,
+      None => return None
+    };
+    Some(self.modules.remove(index).1)
+  }
+
+  /// Returns the handle of `module` if it is recorded.
+  pub(crate) fn find(&self, module: LLVMModuleRef) -> Option<ModuleHandle>
+  {
+    self.modules.iter()
+      .find(|&&(_, other)| other == module)
+      .map(|&(handle, _)| handle)
+  }
+
+  /// Returns the modules, in the order they were added.
+  pub(crate) fn modules(&self) -> Vec<LLVMModuleRef>
+  {
+    self.modules.iter().map(|&(_, module)| module).collect()
+  }
+}
+
+
+/// Add `module` to `engine`, which takes ownership of it, and record it in `modules`.
+pub(crate) fn add_owned(engine: LLVMExecutionEngineRef, modules: &RefCell<OwnedModules>,
+                        module: CSemiBox<Module>) -> ModuleHandle
+{
+  unsafe {
+    let module = module.unwrap();
+    engine::LLVMAddModule(engine, module);
+    modules.borrow_mut().push(module)
+  }
+}
+
+/// Remove the module `handle` identifies from `engine` if it is recorded in `modules`, and
+/// give ownership of it back.
+pub(crate) fn remove_owned<'a>(engine: LLVMExecutionEngineRef, modules: &RefCell<OwnedModules>,
+                               handle: ModuleHandle) -> Result<CSemiBox<'a, Module>, CBox<str>>
+{
+  let mut modules = modules.borrow_mut();
+  let index = match modules.modules.iter().position(|&(other, _)| other == handle) {
+    Some(index) => index,
+    None => return Err("the module is not in this engine".into())
+  };
+  unsafe {
+    let mut out = ptr::null_mut();
+    let mut error = ptr::null_mut();
+    if engine::LLVMRemoveModule(engine, modules.modules[index].1, &mut out, &mut error) != 0 {
+      return Err(CBox::new(error))
+    }
+    modules.modules.remove(index);
+    Ok(CSemiBox::new(out))
+  }
+}
+
+/// Returns the handle of the module `function` is in if it is recorded in `modules`.
+fn find_owned(modules: &RefCell<OwnedModules>, function: &Function) -> Option<ModuleHandle>
+{
+  let parent = unsafe { core::LLVMGetGlobalParent(function.into()) };
+  modules.borrow().find(parent)
+}
+
+
+/// The range of addresses the machine code and data compiled by a JIT can be placed in.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CodeModel
+{
+  /// Let the target choose.
+  Default,
+  /// Let the target choose what is best for a JIT.
+  JITDefault,
+  /// Code and data must be within 2GB of each other.
+  Small,
+  /// Code is in the top 2GB of the address space, as in an OS kernel.
+  Kernel,
+  /// Code must be within 2GB of itself, but data can be anywhere.
+  Medium,
+  /// Code and data can be anywhere.
+  Large
+}
+
+impl From<CodeModel> for LLVMCodeModel
+{
+  fn from(model: CodeModel) -> LLVMCodeModel
+  {
+    match model {
+      CodeModel::Default => LLVMCodeModel::LLVMCodeModelDefault,
+      CodeModel::JITDefault => LLVMCodeModel::LLVMCodeModelJITDefault,
+      CodeModel::Small => LLVMCodeModel::LLVMCodeModelSmall,
+      CodeModel::Kernel => LLVMCodeModel::LLVMCodeModelKernel,
+      CodeModel::Medium => LLVMCodeModel::LLVMCodeModelMedium,
+      CodeModel::Large => LLVMCodeModel::LLVMCodeModelLarge
+    }
+  }
+}
+
+
 /// The options to pass to the MCJIT backend.
-#[derive(Copy, Clone)]
+///
+/// ```rust
+/// use llvm::*;
+/// let options = JitOptions::new().opt_level(3).frame_pointers(true).cpu("haswell");
+/// assert_eq!(options.opt_level, 3);
+/// assert_eq!(options.code_model, CodeModel::JITDefault);
+/// ```
+#[derive(Clone)]
 pub struct JitOptions 
 {
     /// The degree to which optimizations should be done, between 0 and 3.
     ///
     /// 0 represents no optimizations, 3 represents maximum optimization
-    pub opt_level: usize
+    pub opt_level: usize,
+    /// The range of addresses the compiled code and data can be placed in.
+    pub code_model: CodeModel,
+    /// Whether to keep the frame pointer in every function, so profilers and debuggers
+    /// can walk the stack.
+    pub frame_pointers: bool,
+    /// Whether to select instructions with the fast instruction selector, which compiles
+    /// quicker but produces worse code.
+    pub fast_isel: bool,
+    /// The CPU to compile for, such as `haswell`, or `None` for a generic CPU of the
+    /// host's architecture.
+    pub cpu: Option<String>,
+    /// The target features to enable or disable, such as `+avx2,-sse4a`, or `None` for the
+    /// CPU's own features.
+    pub features: Option<String>,
+    /// What to allocate the memory for compiled code and data with, or `None` to let LLVM
+    /// allocate it.
+    pub memory_manager: Option<SharedMemoryManager>
+}
+
+impl JitOptions
+{
+  /// Create the default options, which optimize at level 2 with fast instruction selection
+  /// and without frame pointers, for a generic CPU.
+  pub fn new() -> JitOptions
+  {
+    JitOptions {
+      opt_level: 2,
+      code_model: CodeModel::JITDefault,
+      frame_pointers: false,
+      fast_isel: true,
+      cpu: None,
+      features: None,
+      memory_manager: None
+    }
+  }
+
+  /// Set the degree to which optimizations should be done, between 0 and 3.
+  pub fn opt_level(mut self, opt_level: usize) -> JitOptions
+  {
+    self.opt_level = opt_level;
+    self
+  }
+
+  /// Set the range of addresses the compiled code and data can be placed in.
+  pub fn code_model(mut self, code_model: CodeModel) -> JitOptions
+  {
+    self.code_model = code_model;
+    self
+  }
+
+  /// Set whether to keep the frame pointer in every function.
+  pub fn frame_pointers(mut self, frame_pointers: bool) -> JitOptions
+  {
+    self.frame_pointers = frame_pointers;
+    self
+  }
+
+  /// Set whether to select instructions with the fast instruction selector.
+  pub fn fast_isel(mut self, fast_isel: bool) -> JitOptions
+  {
+    self.fast_isel = fast_isel;
+    self
+  }
+
+  /// Set the CPU to compile for.
+  pub fn cpu(mut self, cpu: &str) -> JitOptions
+  {
+    self.cpu = Some(cpu.to_owned());
+    self
+  }
+
+  /// Set the target features to enable or disable.
+  pub fn features(mut self, features: &str) -> JitOptions
+  {
+    self.features = Some(features.to_owned());
+    self
+  }
+
+  /// Set what to allocate the memory for compiled code and data with.
+  ///
+  /// Each engine should have its own memory manager.
+  pub fn memory_manager<M>(mut self, manager: Rc<RefCell<M>>) -> JitOptions where M: MemoryManager + 'static
+  {
+    let manager: SharedMemoryManager = manager;
+    self.memory_manager = Some(manager);
+    self
+  }
+}
+
+impl PartialEq for JitOptions
+{
+  fn eq(&self, other: &JitOptions) -> bool
+  {
+    let same_manager = match (&self.memory_manager, &other.memory_manager) {
+      (&Some(ref a), &Some(ref b)) => Rc::ptr_eq(a, b),
+      (&None, &None) => true,
+      _ => false
+    };
+    self.opt_level == other.opt_level && self.code_model == other.code_model
+      && self.frame_pointers == other.frame_pointers && self.fast_isel == other.fast_isel
+      && self.cpu == other.cpu && self.features == other.features && same_manager
+  }
+}
+
+impl Eq for JitOptions {}
+
+impl fmt::Debug for JitOptions
+{
+  fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result
+  {
+    fmt.debug_struct("JitOptions")
+      .field("opt_level", &self.opt_level)
+      .field("code_model", &self.code_model)
+      .field("frame_pointers", &self.frame_pointers)
+      .field("fast_isel", &self.fast_isel)
+      .field("cpu", &self.cpu)
+      .field("features", &self.features)
+      .field("memory_manager", &self.memory_manager.as_ref().map(|_| ".."))
+      .finish()
+  }
+}
+
+impl Default for JitOptions
+{
+  fn default() -> JitOptions
+  {
+    JitOptions::new()
+  }
 }
 
 
@@ -124,10 +417,55 @@ pub struct JitOptions
 pub struct JitEngine<'a> 
 {
     engine: LLVMExecutionEngineRef,
+    pub(crate) modules: RefCell<OwnedModules>,
+    pub(crate) redefinable: RefCell<HashMap<String, Redefinable>>,
+    cpu: Option<String>,
+    features: Option<String>,
     marker: PhantomData<&'a ()>
 }
 
-native_ref!{contra JitEngine, engine: LLVMExecutionEngineRef}
+impl<'a, 'b> From<&'a JitEngine<'b>> for LLVMExecutionEngineRef 
+{
+  /// Convert into a native pointer
+  fn from(thing: &'a JitEngine<'b>) -> LLVMExecutionEngineRef 
+  {
+    thing.engine
+  }
+}
+
+impl<'a, 'b> From<&'a mut JitEngine<'b>> for LLVMExecutionEngineRef 
+{
+  /// Convert into a native pointer
+  fn from(thing: &'a mut JitEngine<'b>) -> LLVMExecutionEngineRef 
+  {
+    thing.engine
+  }
+}
+
+impl<'a> From<LLVMExecutionEngineRef> for JitEngine<'a> 
+{
+  /// Convert from a native pointer
+  fn from(ptr: LLVMExecutionEngineRef) -> JitEngine<'a> 
+  {
+    JitEngine {
+      engine: ptr,
+      modules: RefCell::new(OwnedModules::new()),
+      redefinable: RefCell::new(HashMap::new()),
+      cpu: None,
+      features: None,
+      marker: PhantomData
+    }
+  }
+}
+
+impl<'a> Drop for JitEngine<'a> 
+{
+  /// Delete the engine, with the machine code it compiled and every module it owns.
+  fn drop(&mut self) 
+  {
+    unsafe { engine::LLVMDisposeExecutionEngine(self.engine) }
+  }
+}
 
 impl<'a, 'b> JitEngine<'a> 
 {
@@ -139,24 +477,9 @@ impl<'a, 'b> JitEngine<'a>
   		where A:Compile<'b>, R:Compile<'b>, C:FnOnce(extern fn(A) -> R) 
   {
     if cfg!(not(ndebug)) {
-     let ctx = function.get_context();
-     let sig = function.get_signature();
-     assert_eq!(Type::get::<R>(ctx), sig.get_return());
-     let arg = Type::get::<A>(ctx);
-     if let Some(args) = StructType::cast(arg) {
-         assert_eq!(sig.get_params(), args.get_elements());
-     
-     
-     } else {
-       let nparams = sig.get_params().len();
-       if nparams == 1 {
-         assert_eq!(arg, sig.get_params()[0]);
-       } else if nparams == 0 {
-         assert_eq!(arg, Type::get::<()>(ctx));
-       } else {
-         panic!("array type arguments are not yet supported; use a tuple or struct instead");
-       }
-     }
+      if let Err(err) = check_signature::<A, R>(function) {
+        panic!("{}", err)
+      }
     }
     unsafe {
       cb(self.get_function::<A, R>(function));
@@ -173,29 +496,89 @@ impl<'a, 'b> JitEngine<'a>
   /// Returns a pointer to the machine code for the function `function`.
   ///
   /// This is marked as unsafe because the types given as arguments and return could be different
-  /// from their internal representation.
+  /// from their internal representation. It panics if the engine has no machine code for the
+  /// function.
   pub unsafe fn get_function<A, R>(&self, function: &'b Function) -> extern fn(A) -> R 
   {
-    let ptr:&u8 = self.get_global(function);
+    let ptr = engine::LLVMGetPointerToGlobal(self.into(), function.into()) as *const u8;
+    assert!(!ptr.is_null(), "the engine has no machine code for {}", function.get_name());
     mem::transmute(ptr)
   }
   
-  /// Returns a pointer to the machine code for the raw function poionter.
+  /// Returns a pointer to the machine code for the function `function` as the function
+  /// pointer type `F`, or how its signature differs from `F`'s.
+  ///
+  /// This is marked as unsafe because the pointer isn't tied to this engine, so it can be
+  /// called after the machine code is freed. Use `get_jit_function` to get a handle that
+  /// keeps the engine borrowed instead.
+  ///
+  /// The function must be in one of this engine's modules and have machine code, or this
+  /// returns `SignatureError::NotInEngine` or `SignatureError::NoAddress`.
+  ///
+  /// ```rust
+  /// use llvm::*;
+  /// let ctx = Context::new();
+  /// let module = Module::new("typed", &ctx);
+  /// let func = module.add_function("add", Type::get::<fn(u64, u64) -> u64>(&ctx));
+  /// let builder = Builder::new(&ctx);
+  /// builder.position_at_end(func.append("entry"));
+  /// builder.create_ret(builder.create_add(&func[0], &func[1]));
+  ///
+  /// let ee = JitEngine::new(module, JitOptions::new()).unwrap();
+  /// let func = ee.find_function("add").unwrap();
+  /// unsafe {
+  ///   assert!(ee.get_typed_function::<extern "C" fn(u64) -> u64>(func).is_err());
+  ///   let add = ee.get_typed_function::<extern "C" fn(u64, u64) -> u64>(func).unwrap();
+  ///   assert_eq!(add(40, 2), 42);
+  /// }
+  /// ```
+  pub unsafe fn get_typed_function<F>(&self, function: &'b Function) -> Result<F, SignatureError> where F: JitSignature<'b>
+  {
+    if self.get_module_handle(function).is_none() {
+      return Err(SignatureError::NotInEngine)
+    }
+    try!(signature::check_jit::<F>(function));
+    let ptr = engine::LLVMGetPointerToGlobal(self.into(), function.into()) as *const u8;
+    if ptr.is_null() {
+      Err(SignatureError::NoAddress)
+    } else {
+      Ok(F::from_addr(ptr))
+    }
+  }
+  
+  /// Returns a handle to the machine code for the function `function` as the function
+  /// pointer type `F`, which keeps this engine borrowed, or how its signature differs from
+  /// `F`'s.
+  pub fn get_jit_function<'e, F>(&'e self, function: &'b Function) -> Result<JitFunction<'e, F>, SignatureError>
+      where F: JitSignature<'b>
+  {
+    unsafe {
+      let func = try!(self.get_typed_function::<F>(function));
+      Ok(JitFunction::new(func))
+    }
+  }
+  
+  /// Returns a pointer to the machine code for the raw function poionter, or `None` if the
+  /// engine has no machine code for it.
   ///
   /// This is marked as unsafe because the defined function signature and 
   /// return could be different from their internal representation.
   pub unsafe fn get_function_raw(&self, function: &'b Function) -> Option<*const ()> 
   {
-    let ptr:&u8 = self.get_global(function);
-    Some(mem::transmute(ptr))
+    let ptr = engine::LLVMGetPointerToGlobal(self.into(), function.into()) as *const ();
+    if ptr.is_null() {
+      None
+    } else {
+      Some(ptr)
+    }
   }
 }
 
 
-impl<'a, 'b:'a> ExecutionEngine<'a, 'b> for JitEngine<'a> 
+impl<'a> ExecutionEngine<'a> for JitEngine<'a> 
 {
   type Options = JitOptions;
-  fn new(module: &'a Module, options: JitOptions) -> Result<JitEngine<'a>, CBox<str>> 
+  fn new(module: CSemiBox<'a, Module>, options: JitOptions) -> Result<JitEngine<'a>, CBox<str>> 
   {
     unsafe {
       let mut ee = mem::uninitialized();
@@ -207,28 +590,100 @@ impl<'a, 'b:'a> ExecutionEngine<'a, 'b> for JitEngine<'a>
       if target::LLVM_InitializeNativeAsmPrinter() == 1 {
         return Err("failed to initialize native asm printer".into())
       }
+      set_target_attrs(&module, &options.cpu, &options.features);
       
-      let mut options = LLVMMCJITCompilerOptions {
+      let mut native_options = LLVMMCJITCompilerOptions {
         OptLevel: options.opt_level as c_uint,
-        CodeModel: LLVMCodeModel::LLVMCodeModelJITDefault,
-        NoFramePointerElim: 0,
-        EnableFastISel: 1,
-        MCJMM: ptr::null_mut()
+        CodeModel: options.code_model.into(),
+        NoFramePointerElim: options.frame_pointers as c_int,
+        EnableFastISel: options.fast_isel as c_int,
+        MCJMM: options.memory_manager.as_ref().map(memory::to_native).unwrap_or(ptr::null_mut())
       };
       
+      // The engine owns the module from here on, even if it fails to be created.
+      let module = module.unwrap();
       let size = mem::size_of::<LLVMMCJITCompilerOptions>();
       let result = engine::LLVMCreateMCJITCompilerForModule(&mut ee, 
-      		                                                 (&*module).into(), 
-      		                                                 &mut options, 
-      		                                                 size as u64, 
+      		                                                 module, 
+      		                                                 &mut native_options, 
+      		                                                 size, 
       		                                                 &mut out);
       if result == 0 {
-          Ok(ee.into())
+          let mut ee: JitEngine = ee.into();
+          ee.modules.borrow_mut().push(module);
+          ee.cpu = options.cpu;
+          ee.features = options.features;
+          Ok(ee)
       } else {
           Err(CBox::new(out))
       }
     }
   }
+  
+  fn add_module(&self, module: CSemiBox<'a, Module>) -> ModuleHandle 
+  {
+    self.set_target_attrs(&module);
+    add_owned(self.engine, &self.modules, module)
+  }
+  
+  fn remove_module(&mut self, handle: ModuleHandle) -> Result<CSemiBox<'a, Module>, CBox<str>> 
+  {
+    remove_owned(self.engine, &self.modules, handle)
+  }
+  
+  fn get_module_handle(&self, function: &Function) -> Option<ModuleHandle> 
+  {
+    find_owned(&self.modules, function)
+  }
+}
+
+impl<'a> JitEngine<'a>
+{
+  /// Give every function in `module` the CPU and features this engine was created with.
+  pub(crate) fn set_target_attrs(&self, module: &Module)
+  {
+    set_target_attrs(module, &self.cpu, &self.features);
+  }
+}
+
+
+/// Check that the signature of `function` matches a Rust function that takes `A` and
+/// returns `R`, where a tuple or struct `A` stands for its fields as separate parameters.
+pub fn check_signature<'a, A, R>(function: &'a Function) -> Result<(), SignatureError> where A:Compile<'a>, R:Compile<'a> 
+{
+  let ctx = function.get_context();
+  let arg = Type::get::<A>(ctx);
+  let params = if let Some(args) = StructType::cast(arg) {
+    args.get_elements()
+  } else if arg == Type::get::<()>(ctx) {
+    Vec::new()
+  } else {
+    vec![arg]
+  };
+  signature::check(function, &params, Type::get::<R>(ctx))
+}
+
+/// Give every function in `module` the CPU and features given, if they are given.
+///
+/// MCJIT picks the CPU and features for each function from its attributes.
+fn set_target_attrs(module: &Module, cpu: &Option<String>, features: &Option<String>)
+{
+  for func in module {
+    if let Some(ref cpu) = *cpu {
+      set_target_attr(func, "target-cpu", cpu);
+    }
+    if let Some(ref features) = *features {
+      set_target_attr(func, "target-features", features);
+    }
+  }
+}
+
+/// Set the target-dependent attribute `name` of `func` to `value`.
+pub fn set_target_attr(func: &Function, name: &str, value: &str) 
+{
+  util::with_cstr(name, |name| util::with_cstr(value, |value| unsafe {
+    core::LLVMAddTargetDependentFunctionAttr(func.into(), name, value)
+  }))
 }
 
 
@@ -236,10 +691,51 @@ impl<'a, 'b:'a> ExecutionEngine<'a, 'b> for JitEngine<'a>
 pub struct Interpreter<'a> 
 {
   engine: LLVMExecutionEngineRef,
+  pub(crate) modules: RefCell<OwnedModules>,
+  pub(crate) externals: RefCell<Externals>,
   marker: PhantomData<&'a ()>
 }
 
-native_ref!{contra Interpreter, engine: LLVMExecutionEngineRef}
+impl<'a, 'b> From<&'a Interpreter<'b>> for LLVMExecutionEngineRef 
+{
+  /// Convert into a native pointer
+  fn from(thing: &'a Interpreter<'b>) -> LLVMExecutionEngineRef 
+  {
+    thing.engine
+  }
+}
+
+impl<'a, 'b> From<&'a mut Interpreter<'b>> for LLVMExecutionEngineRef 
+{
+  /// Convert into a native pointer
+  fn from(thing: &'a mut Interpreter<'b>) -> LLVMExecutionEngineRef 
+  {
+    thing.engine
+  }
+}
+
+impl<'a> From<LLVMExecutionEngineRef> for Interpreter<'a> 
+{
+  /// Convert from a native pointer
+  fn from(ptr: LLVMExecutionEngineRef) -> Interpreter<'a> 
+  {
+    Interpreter {
+      engine: ptr,
+      modules: RefCell::new(OwnedModules::new()),
+      externals: RefCell::new(Externals::new()),
+      marker: PhantomData
+    }
+  }
+}
+
+impl<'a> Drop for Interpreter<'a> 
+{
+  /// Delete the engine and every module it owns.
+  fn drop(&mut self) 
+  {
+    unsafe { engine::LLVMDisposeExecutionEngine(self.engine) }
+  }
+}
 
 impl<'a> Interpreter<'a> 
 {  
@@ -249,49 +745,108 @@ impl<'a> Interpreter<'a>
   /// To convert the arguments to `GenericValue`s, you should use the 
   /// `GenericValueCast::to_generic` method. To convert the return value 
   /// from a `GenericValue`, you should use the `GenericValueCast::from_generic` method.
-  pub fn run_function(&self, function: &'a Function, 
+  pub fn run_function(&self, function: &Function, 
   	                  args: &[GenericValue<'a>]) -> GenericValue<'a> 
   {
-    let ptr = args.as_ptr() as *mut LLVMGenericValueRef;
-    unsafe { 
-    	engine::LLVMRunFunction(self.into(), 
-    		                      function.into(), 
-    		                      args.len() as c_uint, ptr) 
-    }.into()
+    ExecutionEngine::run_function(self, function, args)
   }
 }
 
 
-impl<'a, 'b:'a> ExecutionEngine<'a, 'b> for Interpreter<'a> 
+impl<'a> ExecutionEngine<'a> for Interpreter<'a> 
 {
   type Options = ();
-  fn new(module: &'a Module, _: ()) -> Result<Interpreter<'a>, CBox<str>> 
+  fn new(module: CSemiBox<'a, Module>, _: ()) -> Result<Interpreter<'a>, CBox<str>> 
   {
     unsafe {
       let mut ee = mem::uninitialized();
       let mut out = mem::zeroed();
       
       engine::LLVMLinkInInterpreter();
-      let ret = engine::LLVMCreateInterpreterForModule(&mut ee, (&*module).into(), &mut out);
+      // The engine owns the module from here on, even if it fails to be created.
+      let module = module.unwrap();
+      let ret = engine::LLVMCreateInterpreterForModule(&mut ee, module, &mut out);
       	
       if ret == 0 {
-          Ok(ee.into())
+          let ee: Interpreter = ee.into();
+          ee.modules.borrow_mut().push(module);
+          Ok(ee)
       } else {
           Err(CBox::new(out))
       }
     }
   }
+  
+  fn add_module(&self, module: CSemiBox<'a, Module>) -> ModuleHandle 
+  {
+    add_owned(self.engine, &self.modules, module)
+  }
+  
+  fn remove_module(&mut self, handle: ModuleHandle) -> Result<CSemiBox<'a, Module>, CBox<str>> 
+  {
+    remove_owned(self.engine, &self.modules, handle)
+  }
+  
+  fn get_module_handle(&self, function: &Function) -> Option<ModuleHandle> 
+  {
+    find_owned(&self.modules, function)
+  }
 }
 
 
+/// What a `GenericValue` is known to hold.
+#[derive(Copy, Clone)]
+enum Kind
+{
+  /// A value of the type given.
+  Typed(LLVMTypeRef),
+  /// A pointer of any type.
+  Pointer,
+  /// Anything, since it was made from a native pointer.
+  Unknown
+}
+
 /// A wrapped value that can be passed to an interpreted function or returned from one
 pub struct GenericValue<'a> 
 {
   value: LLVMGenericValueRef,
+  kind: Kind,
+  /// Whether the integer this value holds is known to fit in 64 bits, even if it is wider.
+  fits: bool,
   marker: PhantomData<&'a ()>
 }
 
-native_ref!(contra GenericValue, value: LLVMGenericValueRef);
+impl<'a, 'b> From<&'a GenericValue<'b>> for LLVMGenericValueRef 
+{
+  /// Convert into a native pointer
+  fn from(thing: &'a GenericValue<'b>) -> LLVMGenericValueRef 
+  {
+    thing.value
+  }
+}
+
+impl<'a, 'b> From<&'a mut GenericValue<'b>> for LLVMGenericValueRef 
+{
+  /// Convert into a native pointer
+  fn from(thing: &'a mut GenericValue<'b>) -> LLVMGenericValueRef 
+  {
+    thing.value
+  }
+}
+
+impl<'a> From<LLVMGenericValueRef> for GenericValue<'a> 
+{
+  /// Convert from a native pointer
+  fn from(ptr: LLVMGenericValueRef) -> GenericValue<'a> 
+  {
+    GenericValue {
+      value: ptr,
+      kind: Kind::Unknown,
+      fits: false,
+      marker: PhantomData
+    }
+  }
+}
 
 impl<'a> Drop for GenericValue<'a> 
 {
@@ -302,6 +857,84 @@ impl<'a> Drop for GenericValue<'a>
   }
 }
 
+impl<'a> GenericValue<'a> 
+{
+  /// Wrap `value`, which holds a value of the type `ty`.
+  fn typed<T>(value: LLVMGenericValueRef, ty: T) -> GenericValue<'a> where T: Into<LLVMTypeRef> 
+  {
+    GenericValue {
+      value: value,
+      kind: Kind::Typed(ty.into()),
+      fits: false,
+      marker: PhantomData
+    }
+  }
+  
+  /// Create a value holding the pointer `ptr`, which can be passed as a pointer of any type.
+  ///
+  /// This is how a buffer or a struct can be given to an interpreted function, since there
+  /// is no way to make a value holding an aggregate.
+  pub fn from_pointer<T>(ptr: *mut T) -> GenericValue<'a> 
+  {
+    GenericValue {
+      value: unsafe { engine::LLVMCreateGenericValueOfPointer(ptr as *mut c_void) },
+      kind: Kind::Pointer,
+      fits: false,
+      marker: PhantomData
+    }
+  }
+  
+  /// Returns the pointer this value holds.
+  pub fn to_pointer<T>(&self) -> *mut T 
+  {
+    unsafe { engine::LLVMGenericValueToPointer(self.into()) as *mut T }
+  }
+  
+  /// Returns the width in bits of the integer this value holds, or `None` if it is known
+  /// to hold something else, like a value made with `from_pointer`.
+  ///
+  /// A value made from a native pointer could hold anything, so its width is only
+  /// meaningful if it is known to hold an integer.
+  pub fn int_width(&self) -> Option<u32> 
+  {
+    match self.kind {
+      Kind::Typed(ty) if unsafe { core::LLVMGetTypeKind(ty) } != LLVMTypeKind::LLVMIntegerTypeKind => None,
+      Kind::Pointer => None,
+      _ => Some(unsafe { engine::LLVMGenericValueIntWidth(self.into()) as u32 })
+    }
+  }
+}
+
+/// Check that `args` can be passed to `function`.
+fn check_args(function: &Function, args: &[GenericValue]) -> Result<(), SignatureError> 
+{
+  let params = function.get_signature().get_params();
+  if params.len() != args.len() {
+    return Err(SignatureError::ParamCount {
+      expected: args.len(),
+      found: params.len()
+    })
+  }
+  for (index, (&param, arg)) in params.iter().zip(args.iter()).enumerate() {
+    let param_ref: LLVMTypeRef = param.into();
+    let expected = match arg.kind {
+      Kind::Typed(ty) if ty != param_ref => {
+        let ty: &Type = ty.into();
+        format!("{}", ty)
+      },
+      Kind::Pointer if unsafe { core::LLVMGetTypeKind(param_ref) } != LLVMTypeKind::LLVMPointerTypeKind =>
+        "a pointer".to_owned(),
+      _ => continue
+    };
+    return Err(SignatureError::Param {
+      index: index,
+      expected: expected,
+      found: format!("{}", param)
+    })
+  }
+  Ok(())
+}
+
 /// A value that can be cast into a `GenericValue` and that a `GenericValue` can be cast into.
 ///
 /// Both these methods require contexts because some `Type` constructors are needed for the
@@ -312,6 +945,12 @@ pub trait GenericValueCast<'a>
   fn to_generic(self, context: &'a Context) -> GenericValue<'a>;
   /// Convert the `GenericValue` into a value of this type again.
   fn from_generic(value: GenericValue<'a>, context: &'a Context) -> Self;
+  /// Convert the `GenericValue` into a value of this type again, or return `None` if the
+  /// value it holds can't be read as one.
+  fn try_from_generic(value: GenericValue<'a>, context: &'a Context) -> Option<Self> where Self: Sized
+  {
+    Some(Self::from_generic(value, context))
+  }
 }
 
 impl<'a> GenericValueCast<'a> for f64 
@@ -320,7 +959,7 @@ impl<'a> GenericValueCast<'a> for f64
   {
     unsafe {
       let ty = core::LLVMDoubleTypeInContext(ctx.into());
-      engine::LLVMCreateGenericValueOfFloat(ty, self).into()
+      GenericValue::typed(engine::LLVMCreateGenericValueOfFloat(ty, self), ty)
     }
   }
   
@@ -328,7 +967,7 @@ impl<'a> GenericValueCast<'a> for f64
   {
     unsafe {
       let ty = core::LLVMDoubleTypeInContext(ctx.into());
-      engine::LLVMGenericValueToFloat(ty, value.into())
+      engine::LLVMGenericValueToFloat(ty, (&value).into())
     }
   }
 }
@@ -339,7 +978,7 @@ impl<'a> GenericValueCast<'a> for f32
   {
     unsafe {
         let ty = core::LLVMFloatTypeInContext(ctx.into());
-        engine::LLVMCreateGenericValueOfFloat(ty, self as f64).into()
+        GenericValue::typed(engine::LLVMCreateGenericValueOfFloat(ty, self as f64), ty)
     }
   }
   
@@ -347,7 +986,7 @@ impl<'a> GenericValueCast<'a> for f32
   {
     unsafe {
         let ty = core::LLVMFloatTypeInContext(ctx.into());
-        engine::LLVMGenericValueToFloat(ty, value.into()) as f32
+        engine::LLVMGenericValueToFloat(ty, (&value).into()) as f32
     }
   }
 }
@@ -359,12 +998,12 @@ macro_rules! generic_int(
       fn to_generic(self, ctx: &'a Context) -> GenericValue<'a> {
         unsafe {
           let ty = <Self as Compile<'a>>::get_type(ctx);
-          engine::LLVMCreateGenericValueOfInt(ty.into(), self as c_ulonglong, $signed as c_int).into()
+          GenericValue::typed(engine::LLVMCreateGenericValueOfInt(ty.into(), self as c_ulonglong, $signed as c_int), ty)
         }
       }
       fn from_generic(value: GenericValue<'a>, _: &'a Context) -> $ty {
         unsafe {
-          engine::LLVMGenericValueToInt(value.into(), $signed as c_int) as $ty
+          engine::LLVMGenericValueToInt((&value).into(), $signed as c_int) as $ty
         }
       }
     }
@@ -381,14 +1020,14 @@ impl<'a> GenericValueCast<'a> for bool
   {
     unsafe {
       let ty = <Self as Compile<'a>>::get_type(ctx);
-      engine::LLVMCreateGenericValueOfInt(ty.into(), self as c_ulonglong, 0).into()
+      GenericValue::typed(engine::LLVMCreateGenericValueOfInt(ty.into(), self as c_ulonglong, 0), ty)
     }
   }
   
   fn from_generic(value: GenericValue<'a>, _: &'a Context) -> bool 
   {
     unsafe {
-      engine::LLVMGenericValueToInt(value.into(), 0) != 0
+      engine::LLVMGenericValueToInt((&value).into(), 0) != 0
     }
   }
 }
@@ -397,3 +1036,113 @@ generic_int!{some i8, u8}
 generic_int!{some i16, u16}
 generic_int!{some i32, u32}
 generic_int!{some i64, u64}
+
+
+macro_rules! generic_wide_int(
+  ($ty:ty, $narrow:ty, $signed:expr) => (
+    impl<'a> GenericValueCast<'a> for $ty {
+      /// Only values that fit in 64 bits can be given to a `GenericValue`, so this panics
+      /// if the value doesn't.
+      fn to_generic(self, ctx: &'a Context) -> GenericValue<'a> {
+        assert!(self as $narrow as $ty == self, "{} does not fit in a GenericValue", self);
+        let mut value = unsafe {
+          let ty = core::LLVMIntTypeInContext(ctx.into(), 128);
+          GenericValue::typed(engine::LLVMCreateGenericValueOfInt(ty, self as $narrow as c_ulonglong, $signed as c_int), ty)
+        };
+        value.fits = true;
+        value
+      }
+      /// This panics if `try_from_generic` would return `None`.
+      fn from_generic(value: GenericValue<'a>, ctx: &'a Context) -> $ty {
+        Self::try_from_generic(value, ctx)
+          .expect("the GenericValue does not hold an integer known to fit in 64 bits")
+      }
+      /// Only the low 64 bits can be read from a `GenericValue`, so this returns `None` if a
+      /// wider value isn't known to fit in them, which only values made by `to_generic` are.
+      /// A wide integer returned by `run_function` can't be read.
+      fn try_from_generic(value: GenericValue<'a>, _: &'a Context) -> Option<$ty> {
+        match value.int_width() {
+          Some(width) if width <= 64 || (width <= 128 && value.fits) => Some(unsafe {
+            engine::LLVMGenericValueToInt((&value).into(), $signed as c_int) as $narrow as $ty
+          }),
+          _ => None
+        }
+      }
+    }
+  );
+);
+
+generic_wide_int!{i128, i64, true}
+generic_wide_int!{u128, u64, false}
+
+impl<'a, T> GenericValueCast<'a> for *mut T 
+{
+  fn to_generic(self, _: &'a Context) -> GenericValue<'a> 
+  {
+    GenericValue::from_pointer(self)
+  }
+  
+  fn from_generic(value: GenericValue<'a>, _: &'a Context) -> *mut T 
+  {
+    value.to_pointer()
+  }
+}
+
+impl<'a, T> GenericValueCast<'a> for *const T 
+{
+  fn to_generic(self, _: &'a Context) -> GenericValue<'a> 
+  {
+    GenericValue::from_pointer(self as *mut T)
+  }
+  
+  fn from_generic(value: GenericValue<'a>, _: &'a Context) -> *const T 
+  {
+    value.to_pointer::<T>() as *const T
+  }
+}
+
+
+/// A list of values that can be cast into the `GenericValue`s a function is run with, which
+/// is implemented for tuples of up to seven values.
+///
+/// ```rust
+/// use llvm::*;
+/// let ctx = Context::new();
+/// let args = (1u32, 2.5f64, true).to_generic_args(&ctx);
+/// assert_eq!(args.len(), 3);
+/// assert_eq!(args[0].int_width(), Some(32));
+/// ```
+pub trait GenericArgs<'a> 
+{
+  /// Create a `GenericValue` from each value in this list.
+  fn to_generic_args(self, context: &'a Context) -> Vec<GenericValue<'a>>;
+}
+
+impl<'a> GenericArgs<'a> for () 
+{
+  fn to_generic_args(self, _: &'a Context) -> Vec<GenericValue<'a>> 
+  {
+    Vec::new()
+  }
+}
+
+macro_rules! generic_args(
+  ($($name:ident = $oname:ident),+) => (
+    impl<'a, $($name),+> GenericArgs<'a> for ($($name,)+) where $($name:GenericValueCast<'a>),+ 
+    {
+      fn to_generic_args(self, context: &'a Context) -> Vec<GenericValue<'a>> 
+      {
+        let ($($oname,)+) = self;
+        vec![$($oname.to_generic(context)),+]
+      }
+    }
+  )
+);
+
+generic_args!{A = a}
+generic_args!{A = a, B = b}
+generic_args!{A = a, B = b, C = c}
+generic_args!{A = a, B = b, C = c, D = d}
+generic_args!{A = a, B = b, C = c, D = d, E = e}
+generic_args!{A = a, B = b, C = c, D = d, E = e, F = f}
+generic_args!{A = a, B = b, C = c, D = d, E = e, F = f, G = g}
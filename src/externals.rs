@@ -0,0 +1,224 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use ffi::core;
+use ffi::execution_engine as engine;
+use ffi::prelude::LLVMValueRef;
+use libc::c_void;
+
+use engine::{GenericValue, Interpreter};
+use module::Module;
+use signature::{self, JitSignature, SignatureError};
+use value::{Function, Value};
+
+
+/// A host function that interpreted code can call.
+struct External
+{
+  addr: *const c_void,
+  check: fn(&Function) -> Result<(), SignatureError>
+}
+
+/// The host functions added to an `Interpreter`.
+pub struct Externals
+{
+  functions: HashMap<String, External>,
+  /// The declarations that have already been bound to host functions.
+  bound: HashSet<LLVMValueRef>
+}
+
+impl Externals
+{
+  /// Create a new set of host functions with nothing in it.
+  pub fn new() -> Externals
+  {
+    Externals {
+      functions: HashMap::new(),
+      bound: HashSet::new()
+    }
+  }
+}
+
+/// Check that `function` has the signature of the host function type `F`.
+fn check_external<F>(function: &Function) -> Result<(), SignatureError> where F: for<'c> JitSignature<'c>
+{
+  signature::check_jit::<F>(function)
+}
+
+
+/// Why the declarations in an `Interpreter`'s modules couldn't all be bound to host
+/// functions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ExternalError
+{
+  /// The functions and globals with these names are declared, but no host function was
+  /// added for them.
+  Unregistered(Vec<String>),
+  /// The host function for `name` doesn't have the signature it is declared with.
+  Signature {
+    name: String,
+    error: SignatureError
+  }
+}
+
+impl fmt::Display for ExternalError
+{
+  fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result
+  {
+    match *self {
+      ExternalError::Unregistered(ref names) =>
+        write!(fmt, "no host functions for: {}", names.join(", ")),
+      ExternalError::Signature { ref name, ref error } =>
+        write!(fmt, "host function {} has the wrong signature: {}", name, error)
+    }
+  }
+}
+
+impl Error for ExternalError
+{
+  fn description(&self) -> &str
+  {
+    "external function error"
+  }
+}
+
+
+impl<'a> Interpreter<'a>
+{
+  /// Let interpreted code call `func` through declarations of the function `name`.
+  ///
+  /// The interpreter converts the `GenericValue`s it passes around to and from the native
+  /// types of `func`'s parameters and return value, which needs an LLVM built with libffi.
+  ///
+  /// ```rust
+  /// use llvm::*;
+  /// extern "C" fn square(x: u64) -> u64 { x * x }
+  ///
+  /// let ctx = Context::new();
+  /// let module = Module::new("script", &ctx);
+  /// let host = module.add_function("square", Type::get::<fn(u64) -> u64>(&ctx));
+  /// let func = module.add_function("run", Type::get::<fn(u64) -> u64>(&ctx));
+  /// let builder = Builder::new(&ctx);
+  /// builder.position_at_end(func.append("entry"));
+  /// builder.create_ret(builder.create_call(host, &[&func[0]]));
+  ///
+  /// let interp = Interpreter::new(module, ()).unwrap();
+  /// interp.add_external("square", square as extern "C" fn(u64) -> u64);
+  /// let func = interp.find_function("run").unwrap();
+  /// let result = interp.run_restricted(func, &[7u64.to_generic(&ctx)]).unwrap();
+  /// assert_eq!(u64::from_generic(result, &ctx), 49);
+  /// ```
+  pub fn add_external<F>(&self, name: &str, func: F) where F: for<'c> JitSignature<'c>
+  {
+    self.externals.borrow_mut().functions.insert(name.to_owned(), External {
+      addr: func.to_addr() as *const c_void,
+      check: check_external::<F>
+    });
+  }
+
+  /// Bind every function and global that the modules in this interpreter declare without
+  /// defining to the host function added for it, or return why some of them couldn't be.
+  ///
+  /// Every declaration needs a host function, apart from LLVM's intrinsics. The interpreter
+  /// looks a function up in the running process by name before it checks the binding, so
+  /// the binding is only sure to be used by `run_restricted`.
+  ///
+  /// The interpreter also remembers what each declaration resolved to the first time it
+  /// is called, and never looks it up again. A declaration that was already called by
+  /// `run_function` keeps calling whatever it found then, so bind the declarations before
+  /// running anything that calls them.
+  pub fn bind_externals(&self) -> Result<(), ExternalError>
+  {
+    let mut externals = self.externals.borrow_mut();
+    let externals = &mut *externals;
+    let mut unregistered = Vec::new();
+    for module in self.modules.borrow().modules() {
+      let module: &Module = module.into();
+      let functions = module.into_iter().map(|func| -> LLVMValueRef { func.into() });
+      let globals = module.global_values().map(|global| -> LLVMValueRef { global.into() });
+      for global in functions.chain(globals) {
+        let value: &Value = global.into();
+        let name = value.get_name().unwrap_or("").to_owned();
+        if unsafe { core::LLVMIsDeclaration(global) } == 0 || name.starts_with("llvm.")
+            || externals.bound.contains(&global) {
+          continue
+        }
+        let is_function = unsafe { !core::LLVMIsAFunction(global).is_null() };
+        match externals.functions.get(&name) {
+          Some(external) if is_function => {
+            if let Err(error) = (external.check)(global.into()) {
+              return Err(ExternalError::Signature {
+                name: name,
+                error: error
+              })
+            }
+            unsafe { engine::LLVMAddGlobalMapping(self.into(), global, external.addr as *mut c_void) };
+            externals.bound.insert(global);
+          },
+          _ => unregistered.push(name)
+        }
+      }
+    }
+    if unregistered.is_empty() {
+      Ok(())
+    } else {
+      Err(ExternalError::Unregistered(unregistered))
+    }
+  }
+
+  /// Bind the declarations in this interpreter's modules to host functions with
+  /// `bind_externals`, then run `function` with the arguments given, or return why they
+  /// couldn't all be bound.
+  ///
+  /// Calls through the declarations go to the host functions added for them rather than to
+  /// anything in the running process with the same name, or one of the interpreter's own
+  /// implementations of C library functions. This only holds for declarations that haven't
+  /// been called by `run_function` before, as the interpreter keeps whatever a declaration
+  /// resolved to the first time it was called.
+  ///
+  /// This is not a sandbox. Interpreted code can still turn any integer into a pointer
+  /// with `inttoptr` and load from or store to it, so it can read and write any memory in
+  /// the process, and only trusted code should be run.
+  pub fn run_restricted(&self, function: &Function, args: &[GenericValue<'a>]) -> Result<GenericValue<'a>, ExternalError>
+  {
+    try!(self.bind_externals());
+    let _renamed = self.rename_bound();
+    Ok(self.run_function(function, args))
+  }
+
+  /// Give every bound declaration in this interpreter's modules a name nothing in the
+  /// running process has until the result is dropped, so the interpreter can only find the
+  /// host function bound to it.
+  fn rename_bound(&self) -> Renamed<'a>
+  {
+    let externals = self.externals.borrow();
+    let mut renamed = Renamed(Vec::new());
+    for module in self.modules.borrow().modules() {
+      let module: &Module = module.into();
+      for func in module {
+        let global: LLVMValueRef = func.into();
+        if externals.bound.contains(&global) {
+          let name = func.get_name().to_owned();
+          func.set_name(&format!("{}.external", name));
+          renamed.0.push((func, name));
+        }
+      }
+    }
+    renamed
+  }
+}
+
+/// The declarations that were renamed while interpreted code runs, with their names, which
+/// are given back when this is dropped.
+struct Renamed<'a>(Vec<(&'a Function, String)>);
+
+impl<'a> Drop for Renamed<'a>
+{
+  fn drop(&mut self)
+  {
+    for &(func, ref name) in &self.0 {
+      func.set_name(name);
+    }
+  }
+}
@@ -0,0 +1,215 @@
+use std::{mem, ptr};
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+use cbox::{CBox, CSemiBox};
+use ffi::core;
+use ffi::execution_engine as engine;
+use ffi::prelude::{LLVMAttributeRef, LLVMValueRef};
+use ffi::{LLVMAtomicOrdering, LLVMAttributeReturnIndex, LLVMTypeKind};
+use libc::{c_uint, c_void};
+
+use builder::Builder;
+use context::GetContext;
+use engine::{self as jit, JitEngine, ModuleHandle};
+use module::Module;
+use util;
+use value::{Attribute, Function};
+
+
+/// The versions of a function that can be redefined while a `JitEngine` runs.
+pub struct Redefinable
+{
+  /// How many versions have been defined, which numbers the next one.
+  versions: usize,
+  /// The modules that defined the versions after the first one and aren't in use anymore.
+  old: Vec<ModuleHandle>,
+  /// The module that defines the current version, if it isn't the first one.
+  current: Option<ModuleHandle>
+}
+
+/// Returns the name of the global that holds the address of the current version of the
+/// function `name`.
+fn slot_name(name: &str) -> String
+{
+  format!("{}.slot", name)
+}
+
+/// Give `stub` and `call`, the call it makes, the calling convention and the return and
+/// parameter attributes of `func`, such as `sret` and `byval`, so the stub is called and
+/// calls each version the way `func` would have been called.
+///
+/// Returns true if any parameter is passed `byval`, which means the copy the stub passes on
+/// is in its own frame.
+unsafe fn copy_abi(func: LLVMValueRef, stub: LLVMValueRef, call: LLVMValueRef) -> bool
+{
+  let conv = core::LLVMGetFunctionCallConv(func);
+  core::LLVMSetFunctionCallConv(stub, conv);
+  core::LLVMSetInstructionCallConv(call, conv);
+  let mut byval = false;
+  let params = core::LLVMCountParams(func);
+  for index in LLVMAttributeReturnIndex..params + 1 {
+    let count = core::LLVMGetAttributeCountAtIndex(func, index);
+    let mut attrs: Vec<LLVMAttributeRef> = vec![ptr::null_mut(); count as usize];
+    core::LLVMGetAttributesAtIndex(func, index, attrs.as_mut_ptr());
+    for attr in attrs {
+      core::LLVMAddAttributeAtIndex(stub, index, attr);
+      core::LLVMAddCallSiteAttribute(call, index, attr);
+    }
+    byval |= !core::LLVMGetEnumAttributeAtIndex(func, index, Attribute::ByVal.kind()).is_null();
+  }
+  byval
+}
+
+/// Give `func` the name of the version `version` of the function `name`.
+fn rename_version(func: &Function, name: &str, version: usize)
+{
+  func.set_name(&format!("{}.v{}", name, version));
+}
+
+impl<'a> JitEngine<'a>
+{
+  /// Make the function `name` redefinable with `redefine`, or return a description of the
+  /// error.
+  ///
+  /// Every call to the function goes through a stub after this, which jumps to whichever
+  /// version is current. This must be done before any code in the function's module is
+  /// compiled, which happens the first time a function or global in it is looked up.
+  pub fn make_redefinable(&self, name: &str) -> Result<(), CBox<str>>
+  {
+    if self.redefinable.borrow().contains_key(name) {
+      return Ok(())
+    }
+    let func = match self.find_function_named(name) {
+      Some(func) if func.get_entry().is_some() => func,
+      _ => return Err(format!("{} is not defined in this engine", name).as_str().into())
+    };
+    let module = unsafe { core::LLVMGetGlobalParent(func.into()) };
+    let ty = unsafe { core::LLVMGlobalGetValueType(func.into()) };
+    rename_version(func, name, 0);
+
+    unsafe {
+      // The stub takes the place of the function, so everything that calls it calls the
+      // stub instead.
+      let stub = util::with_cstr(name, |name| core::LLVMAddFunction(module, name, ty));
+      let stub_func: &Function = stub.into();
+      func.replace_all_uses_with(stub_func);
+
+      // The slot holds the address of the current version, and starts off at the first.
+      let ptr_ty = core::LLVMTypeOf(func.into());
+      let slot = util::with_cstr(&slot_name(name), |slot_name| core::LLVMAddGlobal(module, ptr_ty, slot_name));
+      core::LLVMSetInitializer(slot, func.into());
+
+      let builder = Builder::new(func.get_context());
+      builder.position_at_end(stub_func.append("entry"));
+      let builder_ref = (&*builder).into();
+      let target = core::LLVMBuildLoad2(builder_ref, ptr_ty, slot, b"target\0".as_ptr() as *const _);
+      core::LLVMSetAlignment(target, mem::size_of::<usize>() as c_uint);
+      core::LLVMSetOrdering(target, LLVMAtomicOrdering::LLVMAtomicOrderingAcquire);
+      let mut args: Vec<LLVMValueRef> = (0..core::LLVMCountParams(stub))
+        .map(|index| core::LLVMGetParam(stub, index))
+        .collect();
+      let call = core::LLVMBuildCall2(builder_ref, ty, target, args.as_mut_ptr(), args.len() as c_uint,
+                                      b"\0".as_ptr() as *const _);
+      if !copy_abi(func.into(), stub, call) {
+        core::LLVMSetTailCall(call, 1);
+      }
+      if core::LLVMGetTypeKind(core::LLVMGetReturnType(ty)) == LLVMTypeKind::LLVMVoidTypeKind {
+        core::LLVMBuildRetVoid(builder_ref);
+      } else {
+        core::LLVMBuildRet(builder_ref, call);
+      }
+    }
+
+    self.redefinable.borrow_mut().insert(name.to_owned(), Redefinable {
+      versions: 1,
+      old: Vec::new(),
+      current: None
+    });
+    Ok(())
+  }
+
+  /// Compile the function `name` in `module`, then make every call to the function `name`
+  /// go to it, or return a description of the error.
+  ///
+  /// The function must have been made redefinable with `make_redefinable` first. The
+  /// swap is atomic, so a call on another thread runs either the old or the new version.
+  /// The module that defined the old version is kept until `release_old_versions` is
+  /// called, since the old version may still be running.
+  ///
+  /// This borrows the engine mutably, so it can't be done while a `JitFunction` from it
+  /// exists.
+  pub fn redefine(&mut self, name: &str, module: CSemiBox<'a, Module>) -> Result<(), CBox<str>>
+  {
+    let version = match self.redefinable.borrow().get(name) {
+      Some(redefinable) => redefinable.versions,
+      None => return Err(format!("{} has not been made redefinable", name).as_str().into())
+    };
+    let func = match module.get_function(name) {
+      Some(func) if func.get_entry().is_some() => func,
+      _ => return Err(format!("the new module does not define {}", name).as_str().into())
+    };
+    rename_version(func, name, version);
+    let version_name = format!("{}.v{}", name, version);
+
+    self.set_target_attrs(&module);
+    let handle = jit::add_owned(self.into(), &self.modules, module);
+    unsafe {
+      let addr = util::with_cstr(&version_name, |name| engine::LLVMGetFunctionAddress(self.into(), name));
+      let slot = util::with_cstr(&slot_name(name), |name| engine::LLVMGetGlobalValueAddress(self.into(), name));
+      if addr == 0 || slot == 0 {
+        // The module is deleted when it is dropped, if it can be taken back out.
+        let _ = jit::remove_owned(self.into(), &self.modules, handle);
+        return Err(format!("failed to compile {}", version_name).as_str().into())
+      }
+      let slot = &*(slot as *const AtomicPtr<c_void>);
+      slot.store(addr as *mut c_void, Ordering::Release);
+    }
+
+    let mut redefinable = self.redefinable.borrow_mut();
+    let redefinable = redefinable.get_mut(name).unwrap();
+    redefinable.versions += 1;
+    if let Some(old) = redefinable.current.take() {
+      redefinable.old.push(old);
+    }
+    redefinable.current = Some(handle);
+    Ok(())
+  }
+
+  /// Delete the modules that defined old versions of the function `name`, returning how
+  /// many were deleted.
+  ///
+  /// A module the engine won't give back is kept, and tried again the next time.
+  ///
+  /// Nothing may be running an old version when this is called. The module the function
+  /// was first defined in is never deleted, since the stub lives in it, and the machine
+  /// code of old versions is only freed when the engine is.
+  ///
+  /// This borrows the engine mutably, so it can't be done while a `JitFunction` from it
+  /// exists.
+  pub fn release_old_versions(&mut self, name: &str) -> usize
+  {
+    let engine: engine::LLVMExecutionEngineRef = (&*self).into();
+    let mut redefinable = self.redefinable.borrow_mut();
+    let redefinable = match redefinable.get_mut(name) {
+      Some(redefinable) => redefinable,
+      None => return 0
+    };
+    let count = redefinable.old.len();
+    let modules = &self.modules;
+    redefinable.old.retain(|&handle| jit::remove_owned(engine, modules, handle).is_err());
+    count - redefinable.old.len()
+  }
+
+  /// Find the function with the name given in any module in this engine.
+  fn find_function_named(&self, name: &str) -> Option<&Function>
+  {
+    util::with_cstr(name, |name| unsafe {
+      let mut out = mem::zeroed();
+      if engine::LLVMFindFunction(self.into(), name, &mut out) == 0 {
+        util::ptr_to_null(out)
+      } else {
+        None
+      }
+    })
+  }
+}
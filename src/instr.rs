@@ -0,0 +1,609 @@
+use std::{fmt, mem};
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use ffi::{core, LLVMOpcode, LLVMIntPredicate, LLVMRealPredicate};
+use ffi::prelude::LLVMValueRef;
+use libc::{c_int, c_uint};
+
+use block::BasicBlock;
+use util::{self, CastFrom};
+use value::{Function, Predicate, Value};
+
+
+/// The operation that an instruction performs.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Opcode
+{
+  Ret,
+  Br,
+  Switch,
+  IndirectBr,
+  Invoke,
+  Unreachable,
+  CallBr,
+  FNeg,
+  Add,
+  FAdd,
+  Sub,
+  FSub,
+  Mul,
+  FMul,
+  UDiv,
+  SDiv,
+  FDiv,
+  URem,
+  SRem,
+  FRem,
+  Shl,
+  LShr,
+  AShr,
+  And,
+  Or,
+  Xor,
+  Alloca,
+  Load,
+  Store,
+  GetElementPtr,
+  Trunc,
+  ZExt,
+  SExt,
+  FPToUI,
+  FPToSI,
+  UIToFP,
+  SIToFP,
+  FPTrunc,
+  FPExt,
+  PtrToInt,
+  PtrToAddr,
+  IntToPtr,
+  BitCast,
+  AddrSpaceCast,
+  ICmp,
+  FCmp,
+  PHI,
+  Call,
+  Select,
+  UserOp1,
+  UserOp2,
+  VAArg,
+  ExtractElement,
+  InsertElement,
+  ShuffleVector,
+  ExtractValue,
+  InsertValue,
+  Freeze,
+  Fence,
+  AtomicCmpXchg,
+  AtomicRMW,
+  Resume,
+  LandingPad,
+  CleanupRet,
+  CatchRet,
+  CatchPad,
+  CleanupPad,
+  CatchSwitch
+}
+
+impl From<LLVMOpcode> for Opcode
+{
+  fn from(op: LLVMOpcode) -> Opcode
+  {
+    match op {
+      LLVMOpcode::LLVMRet => Opcode::Ret,
+      LLVMOpcode::LLVMUncondBr | LLVMOpcode::LLVMCondBr => Opcode::Br,
+      LLVMOpcode::LLVMSwitch => Opcode::Switch,
+      LLVMOpcode::LLVMIndirectBr => Opcode::IndirectBr,
+      LLVMOpcode::LLVMInvoke => Opcode::Invoke,
+      LLVMOpcode::LLVMUnreachable => Opcode::Unreachable,
+      LLVMOpcode::LLVMCallBr => Opcode::CallBr,
+      LLVMOpcode::LLVMFNeg => Opcode::FNeg,
+      LLVMOpcode::LLVMAdd => Opcode::Add,
+      LLVMOpcode::LLVMFAdd => Opcode::FAdd,
+      LLVMOpcode::LLVMSub => Opcode::Sub,
+      LLVMOpcode::LLVMFSub => Opcode::FSub,
+      LLVMOpcode::LLVMMul => Opcode::Mul,
+      LLVMOpcode::LLVMFMul => Opcode::FMul,
+      LLVMOpcode::LLVMUDiv => Opcode::UDiv,
+      LLVMOpcode::LLVMSDiv => Opcode::SDiv,
+      LLVMOpcode::LLVMFDiv => Opcode::FDiv,
+      LLVMOpcode::LLVMURem => Opcode::URem,
+      LLVMOpcode::LLVMSRem => Opcode::SRem,
+      LLVMOpcode::LLVMFRem => Opcode::FRem,
+      LLVMOpcode::LLVMShl => Opcode::Shl,
+      LLVMOpcode::LLVMLShr => Opcode::LShr,
+      LLVMOpcode::LLVMAShr => Opcode::AShr,
+      LLVMOpcode::LLVMAnd => Opcode::And,
+      LLVMOpcode::LLVMOr => Opcode::Or,
+      LLVMOpcode::LLVMXor => Opcode::Xor,
+      LLVMOpcode::LLVMAlloca => Opcode::Alloca,
+      LLVMOpcode::LLVMLoad => Opcode::Load,
+      LLVMOpcode::LLVMStore => Opcode::Store,
+      LLVMOpcode::LLVMGetElementPtr => Opcode::GetElementPtr,
+      LLVMOpcode::LLVMTrunc => Opcode::Trunc,
+      LLVMOpcode::LLVMZExt => Opcode::ZExt,
+      LLVMOpcode::LLVMSExt => Opcode::SExt,
+      LLVMOpcode::LLVMFPToUI => Opcode::FPToUI,
+      LLVMOpcode::LLVMFPToSI => Opcode::FPToSI,
+      LLVMOpcode::LLVMUIToFP => Opcode::UIToFP,
+      LLVMOpcode::LLVMSIToFP => Opcode::SIToFP,
+      LLVMOpcode::LLVMFPTrunc => Opcode::FPTrunc,
+      LLVMOpcode::LLVMFPExt => Opcode::FPExt,
+      LLVMOpcode::LLVMPtrToInt => Opcode::PtrToInt,
+      LLVMOpcode::LLVMPtrToAddr => Opcode::PtrToAddr,
+      LLVMOpcode::LLVMIntToPtr => Opcode::IntToPtr,
+      LLVMOpcode::LLVMBitCast => Opcode::BitCast,
+      LLVMOpcode::LLVMAddrSpaceCast => Opcode::AddrSpaceCast,
+      LLVMOpcode::LLVMICmp => Opcode::ICmp,
+      LLVMOpcode::LLVMFCmp => Opcode::FCmp,
+      LLVMOpcode::LLVMPHI => Opcode::PHI,
+      LLVMOpcode::LLVMCall => Opcode::Call,
+      LLVMOpcode::LLVMSelect => Opcode::Select,
+      LLVMOpcode::LLVMUserOp1 => Opcode::UserOp1,
+      LLVMOpcode::LLVMUserOp2 => Opcode::UserOp2,
+      LLVMOpcode::LLVMVAArg => Opcode::VAArg,
+      LLVMOpcode::LLVMExtractElement => Opcode::ExtractElement,
+      LLVMOpcode::LLVMInsertElement => Opcode::InsertElement,
+      LLVMOpcode::LLVMShuffleVector => Opcode::ShuffleVector,
+      LLVMOpcode::LLVMExtractValue => Opcode::ExtractValue,
+      LLVMOpcode::LLVMInsertValue => Opcode::InsertValue,
+      LLVMOpcode::LLVMFreeze => Opcode::Freeze,
+      LLVMOpcode::LLVMFence => Opcode::Fence,
+      LLVMOpcode::LLVMAtomicCmpXchg => Opcode::AtomicCmpXchg,
+      LLVMOpcode::LLVMAtomicRMW => Opcode::AtomicRMW,
+      LLVMOpcode::LLVMResume => Opcode::Resume,
+      LLVMOpcode::LLVMLandingPad => Opcode::LandingPad,
+      LLVMOpcode::LLVMCleanupRet => Opcode::CleanupRet,
+      LLVMOpcode::LLVMCatchRet => Opcode::CatchRet,
+      LLVMOpcode::LLVMCatchPad => Opcode::CatchPad,
+      LLVMOpcode::LLVMCleanupPad => Opcode::CleanupPad,
+      LLVMOpcode::LLVMCatchSwitch => Opcode::CatchSwitch
+    }
+  }
+}
+
+
+/// A single operation inside a basic block.
+pub struct Instruction;
+native_ref!(&Instruction = LLVMValueRef);
+deref!(Instruction, Value);
+impl_display!(Instruction, LLVMPrintValueToString);
+
+impl Instruction
+{
+  /// Returns the operation that this instruction performs.
+  pub fn opcode(&self) -> Opcode
+  {
+    unsafe { core::LLVMGetInstructionOpcode(self.into()) }.into()
+  }
+
+  /// Returns the basic block that contains this instruction, or `None` if it has been
+  /// removed from its block.
+  pub fn parent(&self) -> Option<&BasicBlock>
+  {
+    unsafe { util::ptr_to_null(core::LLVMGetInstructionParent(self.into())) }
+  }
+
+  /// Returns true if this instruction ends a basic block.
+  pub fn is_terminator(&self) -> bool
+  {
+    unsafe { !core::LLVMIsATerminatorInst(self.into()).is_null() }
+  }
+
+  /// Returns the number of operands this instruction takes.
+  pub fn num_operands(&self) -> usize
+  {
+    unsafe { core::LLVMGetNumOperands(self.into()) as usize }
+  }
+
+  /// Returns the operand at `index`, or `None` if there is no such operand.
+  pub fn get_operand(&self, index: usize) -> Option<&Value>
+  {
+    if index < self.num_operands() {
+      unsafe { util::ptr_to_null(core::LLVMGetOperand(self.into(), index as c_uint)) }
+    } else {
+      None
+    }
+  }
+
+  /// Replaces the operand at `index` with `value`.
+  pub fn set_operand(&self, index: usize, value: &Value)
+  {
+    assert!(index < self.num_operands(), "no such operand {} on {:?}", index, self);
+    unsafe { core::LLVMSetOperand(self.into(), index as c_uint, value.into()) }
+  }
+
+  /// Iterate through the operands of this instruction.
+  pub fn operands(&self) -> Operands
+  {
+    Operands {
+      instr: self,
+      index: 0,
+      count: self.num_operands()
+    }
+  }
+
+  /// Unlink this instruction from its basic block without deleting it, and return a
+  /// handle that owns it from then on.
+  ///
+  /// It can be inserted again with `Builder::insert`, and is deleted when the handle is
+  /// dropped otherwise.
+  ///
+  /// This is unsafe because there should be no other reference to this once it is
+  /// deleted, but this can't be guaranteed using Rust semantics.
+  pub unsafe fn remove_from_parent(&self) -> DetachedInstruction
+  {
+    core::LLVMInstructionRemoveFromParent(self.into());
+    DetachedInstruction {
+      instr: self.into(),
+      marker: PhantomData
+    }
+  }
+  
+  /// Unlink this instruction from its basic block and delete it, through the handle
+  /// `remove_from_parent` returns.
+  ///
+  /// This will panic if the instruction is still used, so call `replace_all_uses_with`
+  /// first. It is unsafe for the same reason as `remove_from_parent`.
+  pub unsafe fn erase_from_parent(&self)
+  {
+    assert!(self.is_unused(), "cannot erase {:?} while it is still used", self);
+    self.remove_from_parent().erase()
+  }
+
+  /// Returns the number of blocks this terminator can branch to.
+  pub fn num_successors(&self) -> usize
+  {
+    if self.is_terminator() {
+      unsafe { core::LLVMGetNumSuccessors(self.into()) as usize }
+    } else {
+      0
+    }
+  }
+
+  /// Returns the block at `index` this terminator can branch to, or `None` if there is none.
+  pub fn get_successor(&self, index: usize) -> Option<&BasicBlock>
+  {
+    if index < self.num_successors() {
+      unsafe { Some(core::LLVMGetSuccessor(self.into(), index as c_uint).into()) }
+    } else {
+      None
+    }
+  }
+}
+
+impl CastFrom for Instruction
+{
+  type From = Value;
+  fn cast(value: &Value) -> Option<&Instruction>
+  {
+    unsafe { util::ptr_to_null(core::LLVMIsAInstruction(value.into())) }
+  }
+}
+
+
+/// An instruction that has been removed from its basic block, which is deleted when this
+/// is dropped unless it is inserted again with `Builder::insert`.
+pub struct DetachedInstruction<'a>
+{
+  instr: LLVMValueRef,
+  marker: PhantomData<&'a Instruction>
+}
+
+impl<'a> DetachedInstruction<'a>
+{
+  /// Delete the instruction.
+  ///
+  /// This will panic if the instruction is still used, so call `replace_all_uses_with`
+  /// first.
+  pub fn erase(self)
+  {
+    assert!(self.is_unused(), "cannot erase {:?} while it is still used", &*self);
+  }
+
+  /// Returns the instruction, which the caller is responsible for deleting.
+  pub(crate) fn into_raw(self) -> LLVMValueRef
+  {
+    let instr = self.instr;
+    mem::forget(self);
+    instr
+  }
+}
+
+impl<'a> Deref for DetachedInstruction<'a>
+{
+  type Target = Instruction;
+
+  fn deref(&self) -> &Instruction
+  {
+    self.instr.into()
+  }
+}
+
+impl<'a> Drop for DetachedInstruction<'a>
+{
+  /// Delete the instruction, after replacing any uses left with an undefined value.
+  fn drop(&mut self)
+  {
+    let instr: &Instruction = self.instr.into();
+    if !instr.is_unused() {
+      instr.replace_all_uses_with(Value::new_undef(instr.get_type()));
+    }
+    unsafe { core::LLVMDeleteInstruction(self.instr) }
+  }
+}
+
+
+/// An iterator through the operands of an instruction.
+#[derive(Copy, Clone)]
+pub struct Operands<'a>
+{
+  instr: &'a Instruction,
+  index: usize,
+  count: usize
+}
+
+impl<'a> Iterator for Operands<'a>
+{
+  type Item = &'a Value;
+
+  fn next(&mut self) -> Option<&'a Value>
+  {
+    if self.index < self.count {
+      self.index += 1;
+      self.instr.get_operand(self.index - 1)
+    } else {
+      None
+    }
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>)
+  {
+    let left = self.count - self.index;
+    (left, Some(left))
+  }
+}
+
+impl<'a> DoubleEndedIterator for Operands<'a>
+{
+  fn next_back(&mut self) -> Option<&'a Value>
+  {
+    if self.index < self.count {
+      self.count -= 1;
+      self.instr.get_operand(self.count)
+    } else {
+      None
+    }
+  }
+}
+
+impl<'a> ExactSizeIterator for Operands<'a> {}
+
+
+macro_rules! instr_type(
+  ($(#[$attr:meta])* $name:ident, $func:ident) => (
+    $(#[$attr])*
+    pub struct $name;
+    native_ref!(&$name = LLVMValueRef);
+    deref!($name, Instruction);
+
+    impl CastFrom for $name
+    {
+      type From = Value;
+      fn cast(value: &Value) -> Option<&$name>
+      {
+        unsafe { util::ptr_to_null(core::$func(value.into())) }
+      }
+    }
+  );
+);
+
+instr_type!{
+  /// An instruction that calls a function.
+  CallInst, LLVMIsACallInst
+}
+instr_type!{
+  /// An instruction that reads from memory.
+  LoadInst, LLVMIsALoadInst
+}
+instr_type!{
+  /// An instruction that writes to memory.
+  StoreInst, LLVMIsAStoreInst
+}
+instr_type!{
+  /// An instruction that allocates memory on the stack.
+  AllocaInst, LLVMIsAAllocaInst
+}
+instr_type!{
+  /// An instruction that branches to another block, possibly depending on a condition.
+  BranchInst, LLVMIsABranchInst
+}
+instr_type!{
+  /// An instruction that branches to whichever block matches a value.
+  SwitchInst, LLVMIsASwitchInst
+}
+instr_type!{
+  /// An instruction that returns from the function.
+  ReturnInst, LLVMIsAReturnInst
+}
+instr_type!{
+  /// An instruction that compares two integers or pointers.
+  ICmpInst, LLVMIsAICmpInst
+}
+instr_type!{
+  /// An instruction that compares two floating-point numbers.
+  FCmpInst, LLVMIsAFCmpInst
+}
+instr_type!{
+  /// An instruction that computes the address of a subelement of an aggregate.
+  GetElementPtrInst, LLVMIsAGetElementPtrInst
+}
+
+impl CallInst
+{
+  /// Returns the value being called, which is a `Function` for direct calls.
+  pub fn get_called_value(&self) -> &Value
+  {
+    unsafe { core::LLVMGetCalledValue(self.into()) }.into()
+  }
+
+  /// Returns the function being called, or `None` if this is an indirect call.
+  pub fn get_called_function(&self) -> Option<&Function>
+  {
+    unsafe { util::ptr_to_null(core::LLVMIsAFunction(core::LLVMGetCalledValue(self.into()))) }
+  }
+
+  /// Returns the number of arguments passed to the callee.
+  pub fn num_args(&self) -> usize
+  {
+    // The callee is always the last operand of a call.
+    self.num_operands() - 1
+  }
+
+  /// Returns true if this call is marked as a tail call.
+  pub fn is_tail_call(&self) -> bool
+  {
+    unsafe { core::LLVMIsTailCall(self.into()) != 0 }
+  }
+
+  /// Marks or unmarks this call as a tail call.
+  pub fn set_tail_call(&self, tail: bool)
+  {
+    unsafe { core::LLVMSetTailCall(self.into(), tail as c_int) }
+  }
+}
+
+impl LoadInst
+{
+  /// Returns the pointer that is read from.
+  pub fn get_pointer(&self) -> &Value
+  {
+    self.get_operand(0).unwrap()
+  }
+
+  /// Returns true if this load is volatile.
+  pub fn is_volatile(&self) -> bool
+  {
+    unsafe { core::LLVMGetVolatile(self.into()) != 0 }
+  }
+}
+
+impl StoreInst
+{
+  /// Returns the value that is written.
+  pub fn get_value(&self) -> &Value
+  {
+    self.get_operand(0).unwrap()
+  }
+
+  /// Returns the pointer that is written to.
+  pub fn get_pointer(&self) -> &Value
+  {
+    self.get_operand(1).unwrap()
+  }
+
+  /// Returns true if this store is volatile.
+  pub fn is_volatile(&self) -> bool
+  {
+    unsafe { core::LLVMGetVolatile(self.into()) != 0 }
+  }
+}
+
+impl BranchInst
+{
+  /// Returns true if this branch depends on a condition.
+  pub fn is_conditional(&self) -> bool
+  {
+    unsafe { core::LLVMIsConditional(self.into()) != 0 }
+  }
+
+  /// Returns the condition of this branch, or `None` if it is unconditional.
+  pub fn get_condition(&self) -> Option<&Value>
+  {
+    if self.is_conditional() {
+      unsafe { Some(core::LLVMGetCondition(self.into()).into()) }
+    } else {
+      None
+    }
+  }
+}
+
+impl SwitchInst
+{
+  /// Returns the block that is branched to when no case matches.
+  pub fn get_default(&self) -> &BasicBlock
+  {
+    unsafe { core::LLVMGetSwitchDefaultDest(self.into()) }.into()
+  }
+
+  /// Returns the value that is switched on.
+  pub fn get_condition(&self) -> &Value
+  {
+    self.get_operand(0).unwrap()
+  }
+}
+
+impl ReturnInst
+{
+  /// Returns the value that is returned, or `None` if this returns void.
+  pub fn get_value(&self) -> Option<&Value>
+  {
+    self.get_operand(0)
+  }
+}
+
+impl ICmpInst
+{
+  /// Returns the comparison that is made.
+  pub fn get_predicate(&self) -> Predicate
+  {
+    Predicate::from_int(self.get_int_predicate())
+  }
+
+  /// Returns true if this compares its operands as signed integers.
+  pub fn is_signed(&self) -> bool
+  {
+    match self.get_int_predicate() {
+      LLVMIntPredicate::LLVMIntSLT | LLVMIntPredicate::LLVMIntSLE |
+      LLVMIntPredicate::LLVMIntSGT | LLVMIntPredicate::LLVMIntSGE => true,
+      _ => false
+    }
+  }
+
+  fn get_int_predicate(&self) -> LLVMIntPredicate
+  {
+    unsafe { core::LLVMGetICmpPredicate(self.into()) }
+  }
+}
+
+impl FCmpInst
+{
+  /// Returns the comparison that is made, or `None` if it is unordered or constant.
+  pub fn get_predicate(&self) -> Option<Predicate>
+  {
+    match unsafe { core::LLVMGetFCmpPredicate(self.into()) } {
+      LLVMRealPredicate::LLVMRealOEQ => Some(Predicate::Equal),
+      LLVMRealPredicate::LLVMRealONE => Some(Predicate::NotEqual),
+      LLVMRealPredicate::LLVMRealOGT => Some(Predicate::GreaterThan),
+      LLVMRealPredicate::LLVMRealOGE => Some(Predicate::GreaterThanOrEqual),
+      LLVMRealPredicate::LLVMRealOLT => Some(Predicate::LessThan),
+      LLVMRealPredicate::LLVMRealOLE => Some(Predicate::LessThanOrEqual),
+      _ => None
+    }
+  }
+}
+
+impl GetElementPtrInst
+{
+  /// Returns the pointer that is indexed into.
+  pub fn get_pointer(&self) -> &Value
+  {
+    self.get_operand(0).unwrap()
+  }
+
+  /// Returns true if this address computation is marked as in-bounds.
+  pub fn is_in_bounds(&self) -> bool
+  {
+    unsafe { core::LLVMIsInBounds(self.into()) != 0 }
+  }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+
+/// A function compiled by a JIT, which borrows the engine its machine code is in.
+///
+/// The borrow means the engine can't be dropped, and no module can be removed from it,
+/// while the handle exists, so the machine code can't be freed before it is called.
+///
+/// ```rust
+/// use llvm::*;
+/// let ctx = Context::new();
+/// let module = Module::new("handle", &ctx);
+/// let func = module.add_function("sub", Type::get::<fn(u64, u64) -> u64>(&ctx));
+/// let builder = Builder::new(&ctx);
+/// builder.position_at_end(func.append("entry"));
+/// builder.create_ret(builder.create_sub(&func[0], &func[1]));
+///
+/// let ee = JitEngine::new(module, JitOptions::new()).unwrap();
+/// let func = ee.find_function("sub").unwrap();
+/// let sub = ee.get_jit_function::<extern "C" fn(u64, u64) -> u64>(func).unwrap();
+/// assert_eq!(sub.call(50, 8), 42);
+/// ```
+#[derive(Copy, Clone)]
+pub struct JitFunction<'e, F>
+{
+  func: F,
+  marker: PhantomData<&'e ()>
+}
+
+impl<'e, F> JitFunction<'e, F> where F: Copy
+{
+  /// Wrap the function pointer `func` in a handle that lives for `'e`.
+  ///
+  /// This is marked as unsafe because the machine code `func` points to must stay valid for
+  /// `'e`, which is usually the lifetime of a borrow of the engine it was compiled by.
+  pub unsafe fn new(func: F) -> JitFunction<'e, F>
+  {
+    JitFunction {
+      func: func,
+      marker: PhantomData
+    }
+  }
+
+  /// Returns the function pointer, which is no longer tied to the engine.
+  ///
+  /// This is marked as unsafe because the pointer must not be called after the engine
+  /// frees the machine code.
+  pub unsafe fn into_raw(self) -> F
+  {
+    self.func
+  }
+}
+
+impl<'e, F> fmt::Debug for JitFunction<'e, F> where F: fmt::Debug
+{
+  fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result
+  {
+    write!(fmt, "JitFunction({:?})", self.func)
+  }
+}
+
+macro_rules! jit_function_call(
+  ($($name:ident = $arg:ident),*) => (
+    impl<'e, R, $($name),*> JitFunction<'e, extern "C" fn($($name),*) -> R>
+    {
+      /// Call the function with the arguments given.
+      pub fn call(&self, $($arg: $name),*) -> R
+      {
+        (self.func)($($arg),*)
+      }
+    }
+  )
+);
+
+jit_function_call!{}
+jit_function_call!{A = a}
+jit_function_call!{A = a, B = b}
+jit_function_call!{A = a, B = b, C = c}
+jit_function_call!{A = a, B = b, C = c, D = d}
+jit_function_call!{A = a, B = b, C = c, D = d, E = e}
+jit_function_call!{A = a, B = b, C = c, D = d, E = e, F = f}
+jit_function_call!{A = a, B = b, C = c, D = d, E = e, F = f, G = g}
@@ -10,30 +10,60 @@ extern crate cbox;
 
 #[macro_use]
 mod macros;
+mod analysis;
 mod buffer;
 mod block;
 mod builder;
+mod call_graph;
 mod compile;
 mod context;
+mod debug_info;
+mod dot;
 mod engine;
+mod externals;
+mod hot;
+mod instr;
+mod jit_function;
+mod memory;
+mod metadata;
 mod module;
 mod object;
+mod orc;
+mod pass;
+mod signature;
+mod symbols;
 mod target;
 mod ty;
 mod value;
+mod verify;
 mod util;
 mod phi;
 
 pub use cbox::{CBox, CSemiBox};
+pub use analysis::{DominatorTree, FunctionAnalyses, Loop, LoopInfo};
 pub use builder::Builder;
-pub use block::BasicBlock;
-pub use compile::Compile;
+pub use call_graph::CallGraph;
+pub use block::{BasicBlock, BlockIter};
+pub use compile::{Compile, Decompile, Simd};
 pub use context::{Context, GetContext};
-pub use engine::{JitEngine, JitOptions, Interpreter, ExecutionEngine, GenericValue, GenericValueCast};
+pub use debug_info::{DebugInfoBuilder, DIFile, DIScope, DIType, DIVariable, Encoding, Language};
+pub use instr::{DetachedInstruction, Instruction, Opcode, Operands, CallInst, LoadInst, StoreInst, AllocaInst, BranchInst,
+                SwitchInst, ReturnInst, ICmpInst, FCmpInst, GetElementPtrInst};
+pub use engine::{CodeModel, JitEngine, JitOptions, Interpreter, ExecutionEngine, GenericArgs, GenericValue,
+                 GenericValueCast, ModuleHandle};
+pub use externals::ExternalError;
+pub use jit_function::JitFunction;
+pub use memory::{MemoryManager, PageMemoryManager, SharedMemoryManager};
+pub use metadata::{MDNode, MDString};
 pub use module::{Module};
 pub use object::{ObjectFile, Symbol, Symbols};
+pub use orc::OrcEngine;
+pub use pass::{FunctionPass, FunctionPassManager, ModulePass, Pass, PassManager, PassTiming};
+pub use signature::{JitParam, JitReturn, JitSignature, SignatureError};
+pub use symbols::{MissingSymbols, SymbolResolver};
 pub use target::{TargetData, Target};
 pub use ty::{FunctionType, StructType, Type};
-pub use value::{Arg, Attribute, Value, ValueIter, Function, GlobalValue, Predicate};
+pub use value::{Arg, Attribute, Value, ValueIter, Function, GlobalValue, Predicate, Use, Uses, Users};
 pub use util::CastFrom;
+pub use verify::{FunctionError, VerifierError};
 pub use phi::PhiNode;
@@ -0,0 +1,299 @@
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::rc::Rc;
+use std::{cmp, ptr, str};
+
+use ffi::execution_engine as engine;
+use ffi::execution_engine::LLVMMCJITMemoryManagerRef;
+use libc::{self, c_char, c_int, c_uint, c_void, uintptr_t};
+
+
+/// Allocates the memory that a JIT puts the machine code and data it compiles in.
+///
+/// A JIT asks for every section of each module it compiles before it writes to them, then
+/// finalizes the memory once everything has been written and linked, before any of the
+/// code is run.
+pub trait MemoryManager
+{
+  /// Allocate `size` bytes aligned to `align` bytes for the code section named `name`,
+  /// returning a pointer to them, or null if they couldn't be allocated.
+  ///
+  /// The memory must be writable until `finalize_memory` is called, after which it must be
+  /// executable.
+  fn allocate_code_section(&mut self, size: usize, align: u32, section_id: u32, name: &str) -> *mut u8;
+
+  /// Allocate `size` bytes aligned to `align` bytes for the data section named `name`,
+  /// returning a pointer to them, or null if they couldn't be allocated.
+  ///
+  /// The memory must be writable until `finalize_memory` is called, after which it only
+  /// needs to be readable if `read_only` is true.
+  fn allocate_data_section(&mut self, size: usize, align: u32, section_id: u32, name: &str, read_only: bool) -> *mut u8;
+
+  /// Apply the final permissions to the memory allocated so far, or return a description of
+  /// the error.
+  fn finalize_memory(&mut self) -> Result<(), String>;
+
+  /// Release everything allocated, which is done once the JIT is finished with it.
+  fn destroy(&mut self)
+  {
+  }
+}
+
+/// A memory manager that can be shared between the engine using it and its owner.
+///
+/// The engine borrows it mutably whenever it compiles something, so it must not be
+/// borrowed then: an allocation made while it is borrowed fails, and so does finalizing.
+pub type SharedMemoryManager = Rc<RefCell<dyn MemoryManager>>;
+
+/// Get the memory manager behind the `opaque` pointer of a native memory manager.
+unsafe fn manager<'a>(opaque: *mut c_void) -> &'a SharedMemoryManager
+{
+  &*(opaque as *const SharedMemoryManager)
+}
+
+unsafe fn section_name<'a>(name: *const c_char) -> &'a str
+{
+  if name.is_null() {
+    ""
+  } else {
+    str::from_utf8_unchecked(CStr::from_ptr(name).to_bytes())
+  }
+}
+
+extern "C" fn allocate_code(opaque: *mut c_void, size: uintptr_t, align: c_uint, section_id: c_uint,
+                            name: *const c_char) -> *mut u8
+{
+  unsafe {
+    let name = section_name(name);
+    match manager(opaque).try_borrow_mut() {
+      Ok(mut manager) => manager.allocate_code_section(size as usize, align, section_id, name),
+      Err(_) => ptr::null_mut()
+    }
+  }
+}
+
+extern "C" fn allocate_data(opaque: *mut c_void, size: uintptr_t, align: c_uint, section_id: c_uint,
+                            name: *const c_char, read_only: c_int) -> *mut u8
+{
+  unsafe {
+    let name = section_name(name);
+    match manager(opaque).try_borrow_mut() {
+      Ok(mut manager) => manager.allocate_data_section(size as usize, align, section_id, name, read_only != 0),
+      Err(_) => ptr::null_mut()
+    }
+  }
+}
+
+extern "C" fn finalize(opaque: *mut c_void, error: *mut *mut c_char) -> c_int
+{
+  unsafe {
+    let result = match manager(opaque).try_borrow_mut() {
+      Ok(mut manager) => manager.finalize_memory(),
+      Err(_) => Err("the memory manager is already borrowed".to_owned())
+    };
+    match result {
+      Ok(()) => 0,
+      Err(message) => {
+        // LLVM frees the message, so it has to come from `malloc`.
+        let message = CString::new(message).unwrap_or(CString::new("failed to finalize memory").unwrap());
+        *error = libc::strdup(message.as_ptr());
+        1
+      }
+    }
+  }
+}
+
+extern "C" fn destroy(opaque: *mut c_void)
+{
+  unsafe {
+    // If the manager is borrowed, it is left to release its memory when it is dropped.
+    let manager = Box::from_raw(opaque as *mut SharedMemoryManager);
+    let borrowed = manager.try_borrow_mut();
+    if let Ok(mut manager) = borrowed {
+      manager.destroy();
+    }
+  }
+}
+
+/// Wrap `manager` in a native memory manager that a JIT can use, which keeps it alive until
+/// the JIT destroys it.
+pub fn to_native(manager: &SharedMemoryManager) -> LLVMMCJITMemoryManagerRef
+{
+  let opaque = Box::into_raw(Box::new(manager.clone())) as *mut c_void;
+  unsafe {
+    engine::LLVMCreateSimpleMCJITMemoryManager(opaque, allocate_code, allocate_data, finalize, Some(destroy))
+  }
+}
+
+
+/// One allocation made by a `PageMemoryManager`.
+struct Section
+{
+  ptr: *mut u8,
+  size: usize,
+  protection: c_int
+}
+
+/// A memory manager that gives every section its own pages, and counts how much memory it
+/// has allocated.
+///
+/// Its pages are never writable and executable at once: every section is writable until
+/// the memory is finalized, and then code sections become read-only and executable, and
+/// read-only data sections become read-only.
+///
+/// Each engine should have its own memory manager, so the counts are per engine.
+///
+/// ```rust
+/// use std::cell::RefCell;
+/// use std::rc::Rc;
+/// use llvm::*;
+/// let manager = Rc::new(RefCell::new(PageMemoryManager::new()));
+/// let options = JitOptions::new().memory_manager(manager.clone());
+/// assert_eq!(manager.borrow().allocated(), 0);
+/// ```
+pub struct PageMemoryManager
+{
+  page_size: usize,
+  sections: Vec<Section>,
+  finalized: usize,
+  code_size: usize,
+  data_size: usize
+}
+
+impl PageMemoryManager
+{
+  /// Create a new memory manager that hasn't allocated anything.
+  pub fn new() -> PageMemoryManager
+  {
+    PageMemoryManager {
+      page_size: unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize },
+      sections: Vec::new(),
+      finalized: 0,
+      code_size: 0,
+      data_size: 0
+    }
+  }
+
+  /// Returns how many bytes of code sections have been asked for.
+  pub fn code_size(&self) -> usize
+  {
+    self.code_size
+  }
+
+  /// Returns how many bytes of data sections have been asked for.
+  pub fn data_size(&self) -> usize
+  {
+    self.data_size
+  }
+
+  /// Returns how many bytes of pages have been allocated, which includes the padding at the
+  /// end of each section's pages, and before it for sections aligned to more than a page.
+  pub fn allocated(&self) -> usize
+  {
+    self.sections.iter().map(|section| section.size).sum()
+  }
+
+  /// Map new writable pages for `size` bytes aligned to `align` bytes that will get
+  /// `protection` once finalized.
+  fn allocate(&mut self, size: usize, align: u32, protection: c_int) -> *mut u8
+  {
+    // Pages are aligned to the page size, so a section aligned to more than that needs
+    // enough extra pages to start at the first aligned address in them.
+    let align = cmp::max(align as usize, 1);
+    let padding = if align > self.page_size { align - self.page_size } else { 0 };
+    let size = cmp::max(size, 1) + padding;
+    let size = (size + self.page_size - 1) / self.page_size * self.page_size;
+    unsafe {
+      let ptr = libc::mmap(ptr::null_mut(), size,
+                           libc::PROT_READ | libc::PROT_WRITE,
+                           libc::MAP_PRIVATE | libc::MAP_ANON,
+                           -1, 0);
+      if ptr == libc::MAP_FAILED {
+        return ptr::null_mut()
+      }
+      self.sections.push(Section {
+        ptr: ptr as *mut u8,
+        size: size,
+        protection: protection
+      });
+      let offset = (align - ptr as usize % align) % align;
+      (ptr as *mut u8).offset(offset as isize)
+    }
+  }
+}
+
+impl MemoryManager for PageMemoryManager
+{
+  fn allocate_code_section(&mut self, size: usize, align: u32, _: u32, _: &str) -> *mut u8
+  {
+    let ptr = self.allocate(size, align, libc::PROT_READ | libc::PROT_EXEC);
+    if !ptr.is_null() {
+      self.code_size += size;
+    }
+    ptr
+  }
+
+  fn allocate_data_section(&mut self, size: usize, align: u32, _: u32, _: &str, read_only: bool) -> *mut u8
+  {
+    let protection = if read_only {
+      libc::PROT_READ
+    } else {
+      libc::PROT_READ | libc::PROT_WRITE
+    };
+    let ptr = self.allocate(size, align, protection);
+    if !ptr.is_null() {
+      self.data_size += size;
+    }
+    ptr
+  }
+
+  fn finalize_memory(&mut self) -> Result<(), String>
+  {
+    for section in &self.sections[self.finalized..] {
+      unsafe {
+        if libc::mprotect(section.ptr as *mut c_void, section.size, section.protection) != 0 {
+          return Err(format!("failed to protect the memory at {:?}", section.ptr))
+        }
+        if section.protection & libc::PROT_EXEC != 0 {
+          clear_cache(section.ptr, section.size);
+        }
+      }
+    }
+    self.finalized = self.sections.len();
+    Ok(())
+  }
+
+  fn destroy(&mut self)
+  {
+    for section in self.sections.drain(..) {
+      unsafe { libc::munmap(section.ptr as *mut c_void, section.size) };
+    }
+    self.finalized = 0;
+  }
+}
+
+impl Drop for PageMemoryManager
+{
+  fn drop(&mut self)
+  {
+    self.destroy()
+  }
+}
+
+/// Make sure the instruction cache sees the code just written to `ptr`.
+///
+/// This is only needed on architectures whose instruction caches aren't kept coherent
+/// with their data caches.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+unsafe fn clear_cache(_: *mut u8, _: usize)
+{
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+unsafe fn clear_cache(ptr: *mut u8, size: usize)
+{
+  extern {
+    fn __clear_cache(start: *mut c_char, end: *mut c_char);
+  }
+  __clear_cache(ptr as *mut c_char, ptr.offset(size as isize) as *mut c_char)
+}
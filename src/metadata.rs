@@ -0,0 +1,175 @@
+use std::{mem, ptr, slice, str};
+use std::ops::Deref;
+
+use ffi::core;
+use ffi::prelude::LLVMValueRef;
+use libc::{c_char, c_uint};
+
+use compile::Compile;
+use context::Context;
+use instr::Instruction;
+use module::Module;
+use util::{self, CastFrom};
+use value::Value;
+
+
+/// A string that can be used as an operand of a metadata node.
+pub struct MDString;
+native_ref!(&MDString = LLVMValueRef);
+deref!(MDString, Value);
+
+impl MDString
+{
+  /// Create a new metadata string in the context given.
+  pub fn new<'a>(context: &'a Context, text: &str) -> &'a MDString
+  {
+    let ptr = text.as_ptr() as *const c_char;
+    unsafe { core::LLVMMDStringInContext(context.into(), ptr, text.len() as c_uint) }.into()
+  }
+
+  /// Returns the string this contains.
+  pub fn get_string(&self) -> &str
+  {
+    unsafe {
+      let mut len = 0;
+      let ptr = core::LLVMGetMDString(self.into(), &mut len);
+      str::from_utf8_unchecked(slice::from_raw_parts(ptr as *const u8, len as usize))
+    }
+  }
+}
+
+impl CastFrom for MDString
+{
+  type From = Value;
+  fn cast(value: &Value) -> Option<&MDString>
+  {
+    unsafe { util::ptr_to_null(core::LLVMIsAMDString(value.into())) }
+  }
+}
+
+
+/// A tuple of values and other metadata that can be attached to an instruction or added to
+/// the named metadata of a module.
+pub struct MDNode;
+native_ref!(&MDNode = LLVMValueRef);
+deref!(MDNode, Value);
+
+impl MDNode
+{
+  /// Create a new metadata node in the context given, containing `values`.
+  ///
+  /// A node with no values can be used as a flag, such as for `!nonnull`.
+  pub fn new<'a>(context: &'a Context, values: &[&'a Value]) -> &'a MDNode
+  {
+    let ptr = values.as_ptr() as *mut LLVMValueRef;
+    unsafe { core::LLVMMDNodeInContext(context.into(), ptr, values.len() as c_uint) }.into()
+  }
+
+  /// Create a new `!range` node for integers of the type of `low`, allowing values from
+  /// `low` inclusive to `high` exclusive.
+  pub fn range<'a>(context: &'a Context, low: &'a Value, high: &'a Value) -> &'a MDNode
+  {
+    MDNode::new(context, &[low, high])
+  }
+
+  /// Create a new `!prof` node that gives the relative likelihood of each successor of a
+  /// branch or switch being taken, in order.
+  pub fn branch_weights<'a>(context: &'a Context, weights: &[u32]) -> &'a MDNode
+  {
+    let mut values = vec![&**MDString::new(context, "branch_weights")];
+    values.extend(weights.iter().map(|&weight| weight.compile(context)));
+    MDNode::new(context, &values)
+  }
+
+  /// Returns the number of values in this node.
+  pub fn num_operands(&self) -> usize
+  {
+    unsafe { core::LLVMGetMDNodeNumOperands(self.into()) as usize }
+  }
+
+  /// Returns the values in this node, with `None` for each operand that is null.
+  pub fn get_operands(&self) -> Vec<Option<&Value>>
+  {
+    let mut operands = vec![ptr::null_mut(); self.num_operands()];
+    unsafe { core::LLVMGetMDNodeOperands(self.into(), operands.as_mut_ptr()) };
+    operands.into_iter().map(|operand| unsafe { util::ptr_to_null(operand) }).collect()
+  }
+}
+
+impl CastFrom for MDNode
+{
+  type From = Value;
+  fn cast(value: &Value) -> Option<&MDNode>
+  {
+    unsafe { util::ptr_to_null(core::LLVMIsAMDNode(value.into())) }
+  }
+}
+
+
+impl Context
+{
+  /// Returns the identifier of the metadata kind with the name given, such as `tbaa` or
+  /// `range`, registering it if it is a new kind.
+  pub fn metadata_kind_id(&self, name: &str) -> u32
+  {
+    let ptr = name.as_ptr() as *const c_char;
+    unsafe { core::LLVMGetMDKindIDInContext(self.into(), ptr, name.len() as c_uint) }
+  }
+}
+
+impl Value
+{
+  /// Returns true if this is an instruction with any metadata attached.
+  pub fn has_metadata(&self) -> bool
+  {
+    Instruction::cast(self).is_some() && unsafe { core::LLVMHasMetadata(self.into()) != 0 }
+  }
+
+  /// Returns the metadata of the kind given attached to this instruction, or `None` if
+  /// there isn't any.
+  pub fn get_metadata(&self, kind: u32) -> Option<&MDNode>
+  {
+    assert!(Instruction::cast(self).is_some(), "only instructions have metadata");
+    unsafe { util::ptr_to_null(core::LLVMGetMetadata(self.into(), kind)) }
+  }
+
+  /// Attach `node` to this instruction as metadata of the kind given, replacing any it
+  /// already had of that kind.
+  ///
+  /// This panics if this is not an instruction.
+  pub fn set_metadata(&self, kind: u32, node: &MDNode)
+  {
+    assert!(Instruction::cast(self).is_some(), "only instructions have metadata");
+    unsafe { core::LLVMSetMetadata(self.into(), kind, node.into()) }
+  }
+
+  /// Remove the metadata of the kind given from this instruction.
+  pub fn remove_metadata(&self, kind: u32)
+  {
+    assert!(Instruction::cast(self).is_some(), "only instructions have metadata");
+    unsafe { core::LLVMSetMetadata(self.into(), kind, ptr::null_mut()) }
+  }
+}
+
+impl Module
+{
+  /// Add `node` to the end of the named metadata with the name given, creating it if it
+  /// doesn't exist yet.
+  pub fn add_named_metadata_operand(&self, name: &str, node: &MDNode)
+  {
+    util::with_cstr(name, |ptr| unsafe {
+      core::LLVMAddNamedMetadataOperand(self.into(), ptr, node.into())
+    })
+  }
+
+  /// Returns the nodes in the named metadata with the name given, which is empty if it
+  /// doesn't exist.
+  pub fn get_named_metadata(&self, name: &str) -> Vec<&MDNode>
+  {
+    util::with_cstr(name, |ptr| unsafe {
+      let mut nodes = vec![ptr::null_mut(); core::LLVMGetNamedMetadataNumOperands(self.into(), ptr) as usize];
+      core::LLVMGetNamedMetadataOperands(self.into(), ptr, nodes.as_mut_ptr());
+      nodes.into_iter().map(|node| node.into()).collect()
+    })
+  }
+}
@@ -10,20 +10,21 @@ use std::path::Path;
 use std::process::Command;
 
 use cbox::{CBox, CSemiBox};
-use ffi::analysis::LLVMVerifierFailureAction;
-use ffi::{analysis, core, linker, LLVMModule};
+use ffi::{core, linker, LLVMModule};
 use ffi::bit_writer as writer;
 use ffi::bit_reader as reader;
 use ffi::ir_reader as ir_reader;
 use ffi::prelude::{LLVMValueRef, LLVMModuleRef};
-use ffi::transforms::pass_manager_builder as builder;
 use libc::{c_char, c_uint};
 
 use buffer::MemoryBuffer;
+use compile::Compile;
 use context::{Context, GetContext};
+use pass::PassManager;
 use util;
 use ty::Type;
 use value::{Function, GlobalValue, Value, ValueIter};
+use verify::{self, VerifierError};
 
 /// Represents a single compilation unit of code.
 ///
@@ -80,6 +81,20 @@ impl Module
     })
   }
   
+  /// Add a constant global array to the module with the given name, holding the values given.
+  ///
+  /// This is useful for embedding lookup tables into the module.
+  pub fn add_global_array<'a, T>(&'a self, name: &str, values: &[T]) -> &'a GlobalValue 
+  	   where T: Copy + Compile<'a> + 'a 
+  {
+    let context = self.get_context();
+    let values:Vec<_> = values.iter().map(|&value| value.compile(context)).collect();
+    let array = Value::new_array(T::get_type(context), &values);
+    let global = self.add_global_constant(name, array);
+    unsafe { core::LLVMSetGlobalConstant(global.into(), 1) }
+    global
+  }
+  
   /// Get the global with the name given, or `None` if no global with that name exists.
   pub fn get_global<'a>(&'a self, name: &str) -> Option<&'a GlobalValue> 
   {
@@ -92,9 +107,11 @@ impl Module
   /// Get an iterator of global values
   pub fn global_values<'a>(&'a self) -> ValueIter<&'a GlobalValue>
   {
-  	ValueIter::new(
+  	ValueIter::new_double_ended(
  			unsafe { core::LLVMGetFirstGlobal(self.into()) },
- 			core::LLVMGetNextGlobal
+ 			unsafe { core::LLVMGetLastGlobal(self.into()) },
+ 			core::LLVMGetNextGlobal,
+ 			core::LLVMGetPreviousGlobal
  		) 
   }
   
@@ -177,19 +194,25 @@ impl Module
     CSemiBox::new(unsafe { core::LLVMCloneModule(self.into()) })
   }
   
-  /// Optimize this module with the given optimization level and size level.
+  /// Optimize this module with the given optimization level and size level, returning
+  /// true if the module was changed.
   ///
-  /// This runs passes depending on the levels given.
-  pub fn optimize(&self, opt_level: usize, size_level: usize) {
-    unsafe {
-      let builder = builder::LLVMPassManagerBuilderCreate();
-      builder::LLVMPassManagerBuilderSetOptLevel(builder, opt_level as c_uint);
-      builder::LLVMPassManagerBuilderSetSizeLevel(builder, size_level as c_uint);
-      let pass_manager = core::LLVMCreatePassManager();
-      builder::LLVMPassManagerBuilderPopulateModulePassManager(builder, pass_manager);
-      builder::LLVMPassManagerBuilderDispose(builder);
-      core::LLVMRunPassManager(pass_manager, self.into());
-    }
+  /// This runs passes depending on the levels given. Use `optimize_with` to choose which
+  /// passes are run instead, or to run passes written in Rust alongside these.
+  pub fn optimize(&self, opt_level: usize, size_level: usize) -> bool 
+  {
+    let mut passes = PassManager::new();
+    passes.add_standard(opt_level, size_level);
+    self.optimize_with(&mut passes)
+  }
+  
+  /// Optimize this module with the passes registered in `passes`, including any written
+  /// in Rust, returning true if the module was changed.
+  ///
+  /// The timings of the passes are kept in `passes` afterwards.
+  pub fn optimize_with(&self, passes: &mut PassManager) -> bool 
+  {
+    passes.run(self)
   }
   
   /// Returns the target data of this module represented as a string
@@ -224,19 +247,11 @@ impl Module
   	unsafe { core::LLVMSetDataLayout(self.into(), c_layout.as_ptr()); }
   }
   
-  /// Verify that the module is safe to run, returning a string detailing the error
+  /// Verify that the module is safe to run, returning the problems the verifier found
   /// when an error occurs.
-  pub fn verify(&self) -> Result<(), CBox<str>> 
+  pub fn verify(&self) -> Result<(), VerifierError> 
   {
-    unsafe {
-      let mut error = mem::uninitialized();
-      let action = LLVMVerifierFailureAction::LLVMReturnStatusAction;
-      if analysis::LLVMVerifyModule(self.into(), action, &mut error) == 1 {
-          Err(CBox::new(error))
-      } else {
-          Ok(())
-      }
-    }
+    verify::verify_module(self)
   }
   
   /// Compile the module into an object file at the given location.
@@ -264,20 +279,7 @@ impl Module
   /// This *does not* destroy the source module.
   pub fn link(&self, src: &Module) -> Result<(), CBox<str>> 
   {
-    unsafe {
-      let dest = self.into();
-      let src = src.into();
-      let mut message = mem::uninitialized();
-      
-      let ret = linker::LLVMLinkModules(dest, src, 
-      	                                linker::LLVMLinkerMode::LLVMLinkerPreserveSource, 
-      	                                &mut message);
-      if ret == 1 {
-        Err(CBox::new(message))
-      } else {
-        Ok(())
-      }
-    }
+    unsafe { link_modules(self.into(), core::LLVMCloneModule(src.into())) }
   }
   
   /// Link a module into this module, returning an error string if an error occurs.
@@ -285,20 +287,7 @@ impl Module
   /// This *does* destroy the source module.
   pub fn link_destroy(&self, src: CSemiBox<Module>) -> Result<(), CBox<str>> 
   {
-    unsafe {
-      let dest = self.into();
-      let src = src.as_ptr();
-      let mut message = mem::uninitialized();
-      
-      let ret = linker::LLVMLinkModules(dest, src, 
-      	                                linker::LLVMLinkerMode::LLVMLinkerDestroySource, 
-      	                                &mut message); 
-      if ret == 1 {
-        Err(CBox::new(message))
-      } else {
-        Ok(())
-      }
-    }
+    unsafe { link_modules(self.into(), src.unwrap()) }
   }
   
   /// Dump the module to stderr (for debugging).
@@ -308,6 +297,19 @@ impl Module
   }
 }
 
+/// Link `src` into `dest`, which destroys `src` whether or not it succeeds.
+unsafe fn link_modules(dest: LLVMModuleRef, src: LLVMModuleRef) -> Result<(), CBox<str>>
+{
+  // The linker reports what went wrong to the context's diagnostic handler, so all that
+  // is left to return here is that it failed.
+  if linker::LLVMLinkModules2(dest, src) == 1 {
+    let message = b"failed to link modules\0";
+    Err(CBox::new(core::LLVMCreateMessage(message.as_ptr() as *const c_char)))
+  } else {
+    Ok(())
+  }
+}
+
 get_context!(Module, LLVMGetModuleContext);
 impl_display!(Module, LLVMPrintModuleToString);
 dispose!(Module, LLVMModule, core::LLVMDisposeModule);
@@ -319,9 +321,11 @@ impl<'a> IntoIterator for &'a Module
   /// Iterate through the functions in the module
   fn into_iter(self) -> ValueIter<'a, &'a Function> 
   {    
- 		ValueIter::new(
+ 		ValueIter::new_double_ended(
  			unsafe { core::LLVMGetFirstFunction(self.into()) },
- 			core::LLVMGetNextFunction
+ 			unsafe { core::LLVMGetLastFunction(self.into()) },
+ 			core::LLVMGetNextFunction,
+ 			core::LLVMGetPreviousFunction
  		)  	
   }
 }
@@ -0,0 +1,414 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::mem;
+use std::process;
+use std::ptr;
+use std::sync::mpsc::{self, Sender};
+
+use cbox::{CBox, CSemiBox};
+use ffi::{bit_reader, bit_writer, core, target, LLVMLinkage};
+use ffi::error::{self, LLVMErrorRef};
+use ffi::orc2::*;
+use ffi::orc2::lljit::*;
+use ffi::prelude::LLVMModuleRef;
+use ffi::target_machine::{self, LLVMCodeGenOptLevel, LLVMRelocMode};
+use libc::c_void;
+
+use compile::Compile;
+use engine::{self, JitOptions, ModuleHandle, OwnedModules};
+use module::Module;
+use util;
+use value::Function;
+
+
+/// Take the message out of `error`, which is consumed.
+unsafe fn error_message(error: LLVMErrorRef) -> CBox<str>
+{
+  let message = error::LLVMGetErrorMessage(error);
+  let owned: CBox<str> = util::to_str(message).into();
+  error::LLVMDisposeErrorMessage(message);
+  owned
+}
+
+/// Called by a lazy stub when the function behind it can't be compiled, which leaves it
+/// nothing to return to.
+extern "C" fn lazy_call_failed()
+{
+  process::abort()
+}
+
+/// Send the address `ExecutionSessionLookup` found for the one symbol it was asked for, or
+/// `None` if it failed, to the `Sender` that `ctx` points to.
+extern "C" fn lookup_done(error: LLVMErrorRef, result: LLVMOrcCSymbolMapPairs, count: usize, ctx: *mut c_void)
+{
+  unsafe {
+    let sender = &*(ctx as *const Sender<Option<u64>>);
+    let addr = if !error.is_null() {
+      error::LLVMConsumeError(error);
+      None
+    } else if count == 1 {
+      Some((*result).Sym.Address)
+    } else {
+      None
+    };
+    let _ = sender.send(addr);
+  }
+}
+
+/// Copy `module` into a new context for ORC, which has to own the context its modules
+/// are in, with the data layout and triple of `jit`, and keeping the frame pointer in every
+/// function if `frame_pointers` is true.
+unsafe fn copy_module(jit: LLVMOrcLLJITRef, module: LLVMModuleRef, frame_pointers: bool)
+    -> Result<LLVMOrcThreadSafeModuleRef, CBox<str>>
+{
+  let buffer = bit_writer::LLVMWriteBitcodeToMemoryBuffer(module);
+  let ctx = core::LLVMContextCreate();
+  let mut copy = ptr::null_mut();
+  let failed = bit_reader::LLVMParseBitcodeInContext2(ctx, buffer, &mut copy) != 0;
+  core::LLVMDisposeMemoryBuffer(buffer);
+  if failed {
+    core::LLVMContextDispose(ctx);
+    return Err("failed to copy the module for the ORC engine".into())
+  }
+  core::LLVMSetDataLayout(copy, LLVMOrcLLJITGetDataLayoutStr(jit));
+  core::LLVMSetTarget(copy, LLVMOrcLLJITGetTripleString(jit));
+  if frame_pointers {
+    let mut func = core::LLVMGetFirstFunction(copy);
+    while !func.is_null() {
+      engine::set_target_attr(func.into(), "frame-pointer", "all");
+      func = core::LLVMGetNextFunction(func);
+    }
+  }
+
+  // The module keeps its own reference to the context.
+  let shared = LLVMOrcCreateNewThreadSafeContextFromLLVMContext(ctx);
+  let module = LLVMOrcCreateNewThreadSafeModule(copy, shared);
+  LLVMOrcDisposeThreadSafeContext(shared);
+  Ok(module)
+}
+
+
+/// A JIT built on LLVM's ORC layers, which compiles each module lazily, the first time one
+/// of its functions is called, rather than when it is added.
+///
+/// Modules are added to it incrementally, and every module shares one namespace, so code in
+/// a module can call functions in any other module in the engine, including ones added
+/// after it. Because of this, two modules in the engine can't define the same symbol.
+///
+/// The engine compiles a copy of each module made when the module is added, so changes made
+/// to a module after that aren't compiled.
+///
+/// ```rust
+/// use llvm::*;
+/// let ctx = Context::new();
+/// let module = Module::new("lazy", &ctx);
+/// let func = module.add_function("answer", Type::get::<fn() -> u64>(&ctx));
+/// let builder = Builder::new(&ctx);
+/// builder.position_at_end(func.append("entry"));
+/// builder.create_ret(42u64.compile(&ctx));
+///
+/// let engine = OrcEngine::new(JitOptions::new()).unwrap();
+/// engine.add_module(module).unwrap();
+/// let func = engine.find_function("answer").unwrap();
+/// engine.with_function(func, |answer: extern fn(()) -> u64| assert_eq!(answer(()), 42));
+/// ```
+pub struct OrcEngine<'a>
+{
+  jit: LLVMOrcLLJITRef,
+  call_through: LLVMOrcLazyCallThroughManagerRef,
+  stubs_manager: LLVMOrcIndirectStubsManagerRef,
+  /// The library the lazy stubs for the functions in every module are defined in, which
+  /// are looked up before the main library.
+  stubs: LLVMOrcJITDylibRef,
+  modules: RefCell<OwnedModules>,
+  /// The trackers for the code and the stubs of each module, which free them when it is
+  /// removed.
+  trackers: RefCell<HashMap<ModuleHandle, (LLVMOrcResourceTrackerRef, LLVMOrcResourceTrackerRef)>>,
+  frame_pointers: bool,
+  marker: PhantomData<&'a ()>
+}
+
+impl<'a> OrcEngine<'a>
+{
+  /// Create a new engine for the host with the options given, or return a description of
+  /// the error.
+  ///
+  /// The engine allocates its own memory, so `options` must not have a memory manager.
+  pub fn new(options: JitOptions) -> Result<OrcEngine<'a>, CBox<str>>
+  {
+    if options.memory_manager.is_some() {
+      return Err("the ORC engine cannot use a custom memory manager".into())
+    }
+    unsafe {
+      if target::LLVM_InitializeNativeTarget() == 1 {
+        return Err("failed to initialize native target".into())
+      }
+      if target::LLVM_InitializeNativeAsmPrinter() == 1 {
+        return Err("failed to initialize native asm printer".into())
+      }
+
+      let triple = target_machine::LLVMGetDefaultTargetTriple();
+      let mut target = ptr::null_mut();
+      let mut message = ptr::null_mut();
+      if target_machine::LLVMGetTargetFromTriple(triple, &mut target, &mut message) != 0 {
+        core::LLVMDisposeMessage(triple);
+        return Err(CBox::new(message))
+      }
+      let level = match options.opt_level {
+        0 => LLVMCodeGenOptLevel::LLVMCodeGenLevelNone,
+        1 => LLVMCodeGenOptLevel::LLVMCodeGenLevelLess,
+        2 => LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+        _ => LLVMCodeGenOptLevel::LLVMCodeGenLevelAggressive
+      };
+      let cpu = options.cpu.clone().unwrap_or(String::new());
+      let features = options.features.clone().unwrap_or(String::new());
+      let machine = util::with_cstr(&cpu, |cpu| util::with_cstr(&features, |features| {
+        target_machine::LLVMCreateTargetMachine(target, triple, cpu, features, level,
+                                                LLVMRelocMode::LLVMRelocDefault,
+                                                options.code_model.into())
+      }));
+      core::LLVMDisposeMessage(triple);
+      target_machine::LLVMSetTargetMachineFastISel(machine, options.fast_isel as i32);
+
+      // The builder takes ownership of the target machine, and the JIT of the builder.
+      let builder = LLVMOrcCreateLLJITBuilder();
+      LLVMOrcLLJITBuilderSetJITTargetMachineBuilder(builder,
+        LLVMOrcJITTargetMachineBuilderCreateFromTargetMachine(machine));
+      let mut jit = ptr::null_mut();
+      let error = LLVMOrcCreateLLJIT(&mut jit, builder);
+      if !error.is_null() {
+        return Err(error_message(error))
+      }
+
+      let session = LLVMOrcLLJITGetExecutionSession(jit);
+      let triple = LLVMOrcLLJITGetTripleString(jit);
+      let mut call_through = ptr::null_mut();
+      let error = LLVMOrcCreateLocalLazyCallThroughManager(triple, session,
+                                                           lazy_call_failed as *const () as u64,
+                                                           &mut call_through);
+      if !error.is_null() {
+        error::LLVMConsumeError(LLVMOrcDisposeLLJIT(jit));
+        return Err(error_message(error))
+      }
+      let stubs_manager = LLVMOrcCreateLocalIndirectStubsManager(triple);
+      if stubs_manager.is_null() {
+        LLVMOrcDisposeLazyCallThroughManager(call_through);
+        error::LLVMConsumeError(LLVMOrcDisposeLLJIT(jit));
+        return Err("the ORC engine has no lazy stubs for this target".into())
+      }
+      let stubs = util::with_cstr("stubs", |name| LLVMOrcExecutionSessionCreateBareJITDylib(session, name));
+      Ok(OrcEngine {
+        jit: jit,
+        call_through: call_through,
+        stubs_manager: stubs_manager,
+        stubs: stubs,
+        modules: RefCell::new(OwnedModules::new()),
+        trackers: RefCell::new(HashMap::new()),
+        frame_pointers: options.frame_pointers,
+        marker: PhantomData
+      })
+    }
+  }
+
+  /// Add `module` to this engine, which takes ownership of it, so its functions can be
+  /// found and are compiled when one of them is first called, or return a description of
+  /// the error.
+  ///
+  /// This fails if the module defines a symbol that a module already in the engine
+  /// defines.
+  pub fn add_module(&self, module: CSemiBox<'a, Module>) -> Result<ModuleHandle, CBox<str>>
+  {
+    unsafe {
+      let ptr: LLVMModuleRef = (&*module).into();
+      let copy = try!(copy_module(self.jit, ptr, self.frame_pointers));
+
+      let main = LLVMOrcLLJITGetMainJITDylib(self.jit);
+      let code = LLVMOrcJITDylibCreateResourceTracker(main);
+      let error = LLVMOrcLLJITAddLLVMIRModuleWithRT(self.jit, code, copy);
+      if !error.is_null() {
+        LLVMOrcReleaseResourceTracker(code);
+        return Err(error_message(error))
+      }
+
+      // Calls from outside the engine go through a stub for each function, which compiles
+      // its module the first time it is called.
+      let flags = LLVMJITSymbolGenericFlags::LLVMJITSymbolGenericFlagsExported as u8
+                | LLVMJITSymbolGenericFlags::LLVMJITSymbolGenericFlagsCallable as u8;
+      let mut aliases = Vec::new();
+      for func in &*module {
+        let linkage = core::LLVMGetLinkage(func.into());
+        if core::LLVMIsDeclaration(func.into()) != 0 || linkage == LLVMLinkage::LLVMInternalLinkage
+           || linkage == LLVMLinkage::LLVMPrivateLinkage {
+          continue
+        }
+        let name = util::with_cstr(func.get_name(), |name| LLVMOrcLLJITMangleAndIntern(self.jit, name));
+        // The alias and its target are each given a reference to the name.
+        LLVMOrcRetainSymbolStringPoolEntry(name);
+        aliases.push(LLVMOrcCSymbolAliasMapPair {
+          Name: name,
+          Entry: LLVMOrcCSymbolAliasMapEntry {
+            Name: name,
+            Flags: LLVMJITSymbolFlags { GenericFlags: flags, TargetFlags: 0 }
+          }
+        });
+      }
+      let stubs = LLVMOrcJITDylibCreateResourceTracker(self.stubs);
+      if !aliases.is_empty() {
+        let unit = LLVMOrcLazyReexports(self.call_through, self.stubs_manager, main,
+                                        aliases.as_mut_ptr(), aliases.len());
+        let error = LLVMOrcJITDylibDefine(self.stubs, unit);
+        if !error.is_null() {
+          LLVMOrcDisposeMaterializationUnit(unit);
+          error::LLVMConsumeError(LLVMOrcResourceTrackerRemove(code));
+          LLVMOrcReleaseResourceTracker(code);
+          LLVMOrcReleaseResourceTracker(stubs);
+          return Err(error_message(error))
+        }
+        // Stubs are always defined with the default tracker, so move them to the module's.
+        let default = LLVMOrcJITDylibGetDefaultResourceTracker(self.stubs);
+        LLVMOrcResourceTrackerTransferTo(default, stubs);
+        LLVMOrcReleaseResourceTracker(default);
+      }
+
+      mem::forget(module);
+      let handle = self.modules.borrow_mut().push(ptr);
+      self.trackers.borrow_mut().insert(handle, (code, stubs));
+      Ok(handle)
+    }
+  }
+
+  /// Remove the module `handle` identifies from this engine and give ownership of it back,
+  /// or return a description of the error.
+  ///
+  /// Any code compiled from the module is freed, so nothing in it may be running, no
+  /// pointer to a function in it may be used after this, and no module left in the engine
+  /// may call it.
+  pub fn remove_module(&mut self, handle: ModuleHandle) -> Result<CSemiBox<'a, Module>, CBox<str>>
+  {
+    let (code, stubs) = match self.trackers.borrow_mut().remove(&handle) {
+      Some(trackers) => trackers,
+      None => return Err("the module is not in this engine".into())
+    };
+    let module = self.modules.borrow_mut().remove(handle).unwrap();
+    unsafe {
+      let stubs_error = LLVMOrcResourceTrackerRemove(stubs);
+      let code_error = LLVMOrcResourceTrackerRemove(code);
+      LLVMOrcReleaseResourceTracker(stubs);
+      LLVMOrcReleaseResourceTracker(code);
+      let error = if stubs_error.is_null() { code_error } else {
+        error::LLVMConsumeError(code_error);
+        stubs_error
+      };
+      if error.is_null() {
+        Ok(CSemiBox::new(module))
+      } else {
+        // What is left of the module's code can't be trusted, so the module is deleted.
+        core::LLVMDisposeModule(module);
+        Err(error_message(error))
+      }
+    }
+  }
+
+  /// Returns the handle of the module `function` is in, or `None` if it isn't in this
+  /// engine.
+  pub fn get_module_handle(&self, function: &Function) -> Option<ModuleHandle>
+  {
+    let parent = unsafe { core::LLVMGetGlobalParent(function.into()) };
+    self.modules.borrow().find(parent)
+  }
+
+  /// Attempt to find a function with the name given in the modules in this engine, or
+  /// `None` if there wasn't a function with that name.
+  pub fn find_function(&self, name: &str) -> Option<&Function>
+  {
+    for module in self.modules.borrow().modules() {
+      let func = util::with_cstr(name, |name| unsafe { core::LLVMGetNamedFunction(module, name) });
+      if !func.is_null() && unsafe { core::LLVMIsDeclaration(func) } == 0 {
+        return Some(func.into())
+      }
+    }
+    None
+  }
+
+  /// Returns the address of the symbol `name`, or `None` if no module in this engine
+  /// defines it.
+  ///
+  /// For a function, this is the address of its stub, so its module is compiled the first
+  /// time it is called rather than now. For data, its module is compiled now.
+  pub fn get_symbol_address(&self, name: &str) -> Option<u64>
+  {
+    unsafe {
+      let session = LLVMOrcLLJITGetExecutionSession(self.jit);
+      let mut order = [
+        LLVMOrcCJITDylibSearchOrderElement {
+          JD: self.stubs,
+          JDLookupFlags: LLVMOrcJITDylibLookupFlags::LLVMOrcJITDylibLookupFlagsMatchAllSymbols
+        },
+        LLVMOrcCJITDylibSearchOrderElement {
+          JD: LLVMOrcLLJITGetMainJITDylib(self.jit),
+          JDLookupFlags: LLVMOrcJITDylibLookupFlags::LLVMOrcJITDylibLookupFlagsMatchAllSymbols
+        }
+      ];
+      // The lookup takes the reference to the name.
+      let mut symbols = [LLVMOrcCLookupSetElement {
+        Name: util::with_cstr(name, |name| LLVMOrcLLJITMangleAndIntern(self.jit, name)),
+        LookupFlags: LLVMOrcSymbolLookupFlags::LLVMOrcSymbolLookupFlagsRequiredSymbol
+      }];
+      let (sender, receiver) = mpsc::channel();
+      LLVMOrcExecutionSessionLookup(session, LLVMOrcLookupKind::LLVMOrcLookupKindStatic,
+                                    order.as_mut_ptr(), order.len(),
+                                    symbols.as_mut_ptr(), symbols.len(),
+                                    lookup_done, &sender as *const Sender<Option<u64>> as *mut c_void);
+      receiver.recv().unwrap_or(None).and_then(|addr| if addr == 0 { None } else { Some(addr) })
+    }
+  }
+
+  /// Run the closure `cb` with the machine code for the function `function`.
+  ///
+  /// This will check that the types match at runtime when in debug mode, but not release mode.
+  pub fn with_function<C, A, R>(&self, function: &'a Function, cb: C)
+      where A:Compile<'a>, R:Compile<'a>, C:FnOnce(extern fn(A) -> R)
+  {
+    if cfg!(not(ndebug)) {
+      if let Err(err) = engine::check_signature::<A, R>(function) {
+        panic!("{}", err)
+      }
+    }
+    unsafe {
+      cb(self.get_function::<A, R>(function));
+    }
+  }
+
+  /// Returns a pointer to the machine code for the function `function`.
+  ///
+  /// This is marked as unsafe because the types given as arguments and return could be different
+  /// from their internal representation. It panics if no module in this engine defines the
+  /// function.
+  pub unsafe fn get_function<A, R>(&self, function: &Function) -> extern fn(A) -> R
+  {
+    let addr = self.get_symbol_address(function.get_name())
+      .expect("function is not defined in this engine");
+    mem::transmute(addr as usize)
+  }
+}
+
+impl<'a> Drop for OrcEngine<'a>
+{
+  /// Delete the engine, with the machine code it compiled and every module it owns.
+  fn drop(&mut self)
+  {
+    unsafe {
+      for (_, (code, stubs)) in self.trackers.borrow_mut().drain() {
+        LLVMOrcReleaseResourceTracker(code);
+        LLVMOrcReleaseResourceTracker(stubs);
+      }
+      LLVMOrcDisposeIndirectStubsManager(self.stubs_manager);
+      LLVMOrcDisposeLazyCallThroughManager(self.call_through);
+      error::LLVMConsumeError(LLVMOrcDisposeLLJIT(self.jit));
+      for module in self.modules.borrow().modules() {
+        core::LLVMDisposeModule(module);
+      }
+    }
+  }
+}
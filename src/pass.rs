@@ -0,0 +1,503 @@
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ptr;
+use std::time::{Duration, Instant};
+
+use ffi::core;
+use ffi::error::{self, LLVMErrorRef};
+use ffi::prelude::{LLVMModuleRef, LLVMValueRef};
+use ffi::transforms::pass_builder as builder;
+use libc::{c_int, c_uint};
+
+use module::Module;
+use util;
+use value::Function;
+
+
+/// A transformation that LLVM can run over a module.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Pass
+{
+  /// Promote stack slots to registers, building SSA form (`mem2reg`).
+  PromoteMemoryToRegister,
+  /// Combine and simplify instructions (`instcombine`).
+  InstructionCombining,
+  /// Reorder commutative expressions so constants can be folded (`reassociate`).
+  Reassociate,
+  /// Eliminate redundant instructions and loads (`gvn`).
+  GVN,
+  /// Hoist loop-invariant code out of loops (`licm`).
+  LICM,
+  /// Merge and remove redundant blocks and branches (`simplifycfg`).
+  CFGSimplification,
+  /// Remove stores that are overwritten before they are read (`dse`).
+  DeadStoreElimination,
+  /// Remove instructions unless they are proven to be needed (`adce`).
+  AggressiveDCE,
+  /// Propagate constants through the control flow graph (`sccp`).
+  SCCP,
+  /// Turn recursive tail calls into loops (`tailcallelim`).
+  TailCallElimination,
+  /// Unroll loops with a known trip count (`loop-unroll`).
+  LoopUnroll,
+  /// Vectorize loops (`loop-vectorize`).
+  LoopVectorize,
+  /// Vectorize straight-line code (`slp-vectorizer`).
+  SLPVectorize,
+  /// Inline functions whose cost is below LLVM's default threshold (`inline`).
+  ///
+  /// LLVM only reads the threshold set with `PassManager::inline_threshold` in the
+  /// inliner of its standard pipelines, so use `add_standard` to inline with another one.
+  Inline,
+  /// Inline functions that are marked as always inline (`always-inline`).
+  AlwaysInline,
+  /// Delete functions and globals that are never used (`globaldce`).
+  GlobalDCE,
+  /// Merge identical constant globals (`constmerge`).
+  ConstantMerge
+}
+
+impl Pass
+{
+  /// Returns the name LLVM's tools use for this pass, such as `mem2reg`.
+  pub fn name(self) -> &'static str
+  {
+    match self {
+      Pass::PromoteMemoryToRegister => "mem2reg",
+      Pass::InstructionCombining => "instcombine",
+      Pass::Reassociate => "reassociate",
+      Pass::GVN => "gvn",
+      Pass::LICM => "licm",
+      Pass::CFGSimplification => "simplifycfg",
+      Pass::DeadStoreElimination => "dse",
+      Pass::AggressiveDCE => "adce",
+      Pass::SCCP => "sccp",
+      Pass::TailCallElimination => "tailcallelim",
+      Pass::LoopUnroll => "loop-unroll",
+      Pass::LoopVectorize => "loop-vectorize",
+      Pass::SLPVectorize => "slp-vectorizer",
+      Pass::Inline => "inline",
+      Pass::AlwaysInline => "always-inline",
+      Pass::GlobalDCE => "globaldce",
+      Pass::ConstantMerge => "constmerge"
+    }
+  }
+
+  /// Returns true if this pass works on a whole module rather than one function at a
+  /// time, so it can't be run by a `FunctionPassManager`.
+  pub fn is_module_pass(self) -> bool
+  {
+    self.function_pipeline().is_none()
+  }
+
+  /// Returns how this pass is written in a pipeline of function passes, or `None` if it is
+  /// a module pass.
+  fn function_pipeline(self) -> Option<&'static str>
+  {
+    match self {
+      Pass::LICM => Some("loop-mssa(licm)"),
+      Pass::Inline | Pass::AlwaysInline | Pass::GlobalDCE | Pass::ConstantMerge => None,
+      _ => Some(self.name())
+    }
+  }
+
+  /// Returns how this pass, which must be a module pass, is written in a pipeline.
+  fn module_pipeline(self) -> &'static str
+  {
+    match self {
+      Pass::Inline => "cgscc(inline)",
+      _ => self.name()
+    }
+  }
+}
+
+/// Returns the pipeline LLVM runs for the optimization level and size level given.
+fn standard_pipeline(opt_level: usize, size_level: usize) -> String
+{
+  match size_level {
+    0 => format!("default<O{}>", cmp::min(opt_level, 3)),
+    1 => "default<Os>".to_owned(),
+    _ => "default<Oz>".to_owned()
+  }
+}
+
+/// Hash the structure of `func`: its blocks, and the instructions in them with their
+/// opcodes, types and operands.
+unsafe fn hash_function<H>(func: LLVMValueRef, hasher: &mut H) where H: Hasher
+{
+  (func as usize).hash(hasher);
+  let mut block = core::LLVMGetFirstBasicBlock(func);
+  while !block.is_null() {
+    (block as usize).hash(hasher);
+    let mut instr = core::LLVMGetFirstInstruction(block);
+    while !instr.is_null() {
+      (instr as usize).hash(hasher);
+      (core::LLVMGetInstructionOpcode(instr) as u32).hash(hasher);
+      (core::LLVMTypeOf(instr) as usize).hash(hasher);
+      for index in 0..core::LLVMGetNumOperands(instr) {
+        (core::LLVMGetOperand(instr, index as c_uint) as usize).hash(hasher);
+      }
+      instr = core::LLVMGetNextInstruction(instr);
+    }
+    block = core::LLVMGetNextBasicBlock(block);
+  }
+}
+
+/// Returns a hash of the structure of `module`: its globals with their initializers, and
+/// the structure of each function.
+///
+/// LLVM's pass builder doesn't say whether anything changed, so this is compared from
+/// before and after running passes. It is much cheaper than printing the module, but
+/// misses changes that only touch attributes, flags or metadata.
+fn module_hash(module: &Module) -> u64
+{
+  let mut hasher = DefaultHasher::new();
+  unsafe {
+    let module: LLVMModuleRef = module.into();
+    let mut global = core::LLVMGetFirstGlobal(module);
+    while !global.is_null() {
+      (global as usize).hash(&mut hasher);
+      (core::LLVMGetInitializer(global) as usize).hash(&mut hasher);
+      global = core::LLVMGetNextGlobal(global);
+    }
+    let mut func = core::LLVMGetFirstFunction(module);
+    while !func.is_null() {
+      hash_function(func, &mut hasher);
+      func = core::LLVMGetNextFunction(func);
+    }
+  }
+  hasher.finish()
+}
+
+/// Returns a hash of the structure of `func`, as `module_hash` does for a module.
+fn function_hash(func: &Function) -> u64
+{
+  let mut hasher = DefaultHasher::new();
+  unsafe { hash_function(func.into(), &mut hasher) };
+  hasher.finish()
+}
+
+/// Panic with the message of `error` if running `pipeline` failed, which only happens if
+/// LLVM couldn't parse it.
+unsafe fn check_pipeline(error: LLVMErrorRef, pipeline: &str)
+{
+  if !error.is_null() {
+    let message = error::LLVMGetErrorMessage(error);
+    let text = util::to_str(message).to_owned();
+    error::LLVMDisposeErrorMessage(message);
+    panic!("couldn't run the passes {}: {}", pipeline, text)
+  }
+}
+
+
+/// A transformation written in Rust that runs over one function at a time.
+pub trait FunctionPass
+{
+  /// Returns the name of this pass, for reporting how long it took.
+  fn name(&self) -> &str;
+
+  /// Run this pass over `func`, returning true if it changed it.
+  fn run(&mut self, func: &Function) -> bool;
+}
+
+/// A transformation written in Rust that runs over a whole module.
+pub trait ModulePass
+{
+  /// Returns the name of this pass, for reporting how long it took.
+  fn name(&self) -> &str;
+
+  /// Run this pass over `module`, returning true if it changed it.
+  fn run(&mut self, module: &Module) -> bool;
+}
+
+/// How long one pass, or one run of consecutive passes from LLVM, took the last time a
+/// `PassManager` was run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PassTiming
+{
+  /// The name of the pass, or the names of LLVM's passes separated by commas.
+  pub name: String,
+  /// How long the pass took to run.
+  pub time: Duration,
+  /// Whether the pass changed the module.
+  pub changed: bool
+}
+
+
+/// One step of a `PassManager`.
+enum Stage
+{
+  Pass(Pass),
+  Standard(usize, usize),
+  Function(Box<dyn FunctionPass>),
+  Module(Box<dyn ModulePass>)
+}
+
+impl Stage
+{
+  fn name(&self) -> String
+  {
+    match *self {
+      Stage::Pass(pass) => pass.name().to_owned(),
+      Stage::Standard(opt_level, size_level) => format!("standard -O{} -s{}", opt_level, size_level),
+      Stage::Function(ref pass) => pass.name().to_owned(),
+      Stage::Module(ref pass) => pass.name().to_owned()
+    }
+  }
+
+  /// Returns true if this step is made of LLVM's passes.
+  fn is_native(&self) -> bool
+  {
+    match *self {
+      Stage::Pass(_) | Stage::Standard(..) => true,
+      Stage::Function(_) | Stage::Module(_) => false
+    }
+  }
+
+  /// Run this step, which must be written in Rust, over `module`, returning true if it
+  /// changed it.
+  fn run(&mut self, module: &Module) -> bool
+  {
+    match *self {
+      Stage::Function(ref mut pass) => {
+        let mut changed = false;
+        for func in module {
+          if func.get_entry().is_some() {
+            changed |= pass.run(func);
+          }
+        }
+        changed
+      },
+      Stage::Module(ref mut pass) => pass.run(module),
+      Stage::Pass(_) | Stage::Standard(..) => unreachable!()
+    }
+  }
+}
+
+/// Returns the pipeline that runs the native steps in `stages` in order, with consecutive
+/// function passes sharing one `function(...)` adaptor so they run one function at a time.
+fn native_pipeline(stages: &[Stage]) -> String
+{
+  let mut pipeline = Vec::new();
+  let mut functions = Vec::new();
+  for stage in stages {
+    if let Stage::Pass(pass) = *stage {
+      if let Some(function) = pass.function_pipeline() {
+        functions.push(function);
+        continue
+      }
+    }
+    if !functions.is_empty() {
+      pipeline.push(format!("function({})", functions.join(",")));
+      functions.clear();
+    }
+    match *stage {
+      Stage::Pass(pass) => pipeline.push(pass.module_pipeline().to_owned()),
+      Stage::Standard(opt_level, size_level) => pipeline.push(standard_pipeline(opt_level, size_level)),
+      Stage::Function(_) | Stage::Module(_) => unreachable!()
+    }
+  }
+  if !functions.is_empty() {
+    pipeline.push(format!("function({})", functions.join(",")));
+  }
+  pipeline.join(",")
+}
+
+/// Run LLVM's passes in `pipeline` over `module`, with the inliner in standard pipelines
+/// using `inline_threshold` if it is given, returning true if they changed it.
+fn run_pipeline(module: &Module, pipeline: &str, inline_threshold: Option<u32>) -> bool
+{
+  let before = module_hash(module);
+  unsafe {
+    let options = builder::LLVMCreatePassBuilderOptions();
+    if let Some(threshold) = inline_threshold {
+      builder::LLVMPassBuilderOptionsSetInlinerThreshold(options, threshold as c_int);
+    }
+    let error = util::with_cstr(pipeline, |text| {
+      builder::LLVMRunPasses(module.into(), text, ptr::null_mut(), options)
+    });
+    builder::LLVMDisposePassBuilderOptions(options);
+    check_pipeline(error, pipeline);
+  }
+  module_hash(module) != before
+}
+
+/// An ordered list of passes to run over a whole module, which can mix LLVM's passes with
+/// passes written in Rust.
+///
+/// ```rust
+/// use llvm::*;
+/// let ctx = Context::new();
+/// let module = Module::new("passes", &ctx);
+/// let mut passes = PassManager::new();
+/// passes.add(Pass::PromoteMemoryToRegister).add(Pass::InstructionCombining).add(Pass::GVN);
+/// passes.run(&module);
+/// ```
+pub struct PassManager
+{
+  stages: Vec<Stage>,
+  inline_threshold: Option<u32>,
+  timings: Vec<PassTiming>
+}
+
+impl PassManager
+{
+  /// Create a new pass manager with no passes.
+  pub fn new() -> PassManager
+  {
+    PassManager {
+      stages: Vec::new(),
+      inline_threshold: None,
+      timings: Vec::new()
+    }
+  }
+
+  /// Add `pass` to the end of the passes to run.
+  pub fn add(&mut self, pass: Pass) -> &mut PassManager
+  {
+    self.stages.push(Stage::Pass(pass));
+    self
+  }
+
+  /// Add every pass in `passes` to the end of the passes to run, in order.
+  pub fn add_all(&mut self, passes: &[Pass]) -> &mut PassManager
+  {
+    self.stages.extend(passes.iter().map(|&pass| Stage::Pass(pass)));
+    self
+  }
+
+  /// Add the passes LLVM runs for the optimization level and size level given to the end
+  /// of the passes to run.
+  ///
+  /// The inliner in them uses the threshold set with `inline_threshold`, or the one LLVM
+  /// picks for the levels if none is set.
+  pub fn add_standard(&mut self, opt_level: usize, size_level: usize) -> &mut PassManager
+  {
+    self.stages.push(Stage::Standard(opt_level, size_level));
+    self
+  }
+
+  /// Set the cost below which the inliner in the standard pipelines inlines a function,
+  /// where 225 is LLVM's default at level 2.
+  pub fn inline_threshold(&mut self, threshold: u32) -> &mut PassManager
+  {
+    self.inline_threshold = Some(threshold);
+    self
+  }
+
+  /// Add `pass` to the end of the passes to run, to be run over every function in the
+  /// module that has a body.
+  pub fn add_function_pass<P>(&mut self, pass: P) -> &mut PassManager where P: FunctionPass + 'static
+  {
+    self.stages.push(Stage::Function(Box::new(pass)));
+    self
+  }
+
+  /// Add `pass` to the end of the passes to run.
+  pub fn add_module_pass<P>(&mut self, pass: P) -> &mut PassManager where P: ModulePass + 'static
+  {
+    self.stages.push(Stage::Module(Box::new(pass)));
+    self
+  }
+
+  /// Run the passes over `module` in order, returning true if any of them changed it.
+  ///
+  /// Consecutive passes from LLVM are run together in one pipeline. This takes `&mut self`
+  /// because the passes written in Rust are run through `&mut self` and the timings are
+  /// kept here, which is also why a `PassManager` isn't `Clone`: those passes are boxed
+  /// trait objects that can't be cloned.
+  pub fn run(&mut self, module: &Module) -> bool
+  {
+    self.timings.clear();
+    let mut changed = false;
+    let mut index = 0;
+    while index < self.stages.len() {
+      let start = Instant::now();
+      let native = self.stages[index..].iter().take_while(|stage| stage.is_native()).count();
+      let (name, stage_changed) = if native > 0 {
+        let stages = &self.stages[index..index + native];
+        let names: Vec<String> = stages.iter().map(Stage::name).collect();
+        index += native;
+        (names.join(", "), run_pipeline(module, &native_pipeline(stages), self.inline_threshold))
+      } else {
+        let stage = &mut self.stages[index];
+        index += 1;
+        (stage.name(), stage.run(module))
+      };
+      self.timings.push(PassTiming {
+        name: name,
+        time: start.elapsed(),
+        changed: stage_changed
+      });
+      changed |= stage_changed;
+    }
+    changed
+  }
+
+  /// Returns how long each pass, or each run of consecutive passes from LLVM, took the
+  /// last time this was run, in the order they ran.
+  pub fn timings(&self) -> &[PassTiming]
+  {
+    &self.timings
+  }
+}
+
+impl fmt::Debug for PassManager
+{
+  fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result
+  {
+    let names: Vec<String> = self.stages.iter().map(Stage::name).collect();
+    fmt.debug_struct("PassManager")
+      .field("passes", &names)
+      .field("inline_threshold", &self.inline_threshold)
+      .finish()
+  }
+}
+
+
+/// Runs passes over the functions of one module, one function at a time.
+///
+/// This is useful for optimizing each function as soon as it is built, such as in a JIT
+/// that builds functions lazily.
+pub struct FunctionPassManager<'a>
+{
+  pipeline: String,
+  marker: PhantomData<&'a ()>
+}
+
+impl<'a> FunctionPassManager<'a>
+{
+  /// Create a pass manager that runs `passes` in order over functions in `module`.
+  ///
+  /// This panics if any of the passes is a module pass.
+  pub fn new(_module: &'a Module, passes: &[Pass]) -> FunctionPassManager<'a>
+  {
+    let pipeline: Vec<&str> = passes.iter().map(|&pass| match pass.function_pipeline() {
+      Some(pipeline) => pipeline,
+      None => panic!("{:?} cannot be run on one function at a time", pass)
+    }).collect();
+    FunctionPassManager {
+      pipeline: pipeline.join(","),
+      marker: PhantomData
+    }
+  }
+
+  /// Run the passes over `func`, which must be in the module this was made for, returning
+  /// true if any of them changed it.
+  pub fn run(&self, func: &'a Function) -> bool
+  {
+    let before = function_hash(func);
+    unsafe {
+      let options = builder::LLVMCreatePassBuilderOptions();
+      let error = util::with_cstr(&self.pipeline, |text| {
+        builder::LLVMRunPassesOnFunction(func.into(), text, ptr::null_mut(), options)
+      });
+      builder::LLVMDisposePassBuilderOptions(options);
+      check_pipeline(error, &self.pipeline);
+    }
+    function_hash(func) != before
+  }
+}
@@ -0,0 +1,194 @@
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use libc::c_char;
+
+use compile::Compile;
+use context::{Context, GetContext};
+use ty::Type;
+use value::Function;
+
+
+/// A type that Rust passes to an `extern "C"` function the same way LLVM passes the type it
+/// compiles to, so a JIT'd function can take it as a parameter.
+///
+/// Tuples compile to LLVM structs, but Rust doesn't lay them out or pass them the same way,
+/// so they can't be parameters.
+///
+/// This is unsafe to implement because nothing checks that the two agree.
+pub unsafe trait JitParam<'a>: Compile<'a>
+{
+}
+
+/// A type that Rust returns from an `extern "C"` function the same way LLVM returns the type
+/// it compiles to, so a JIT'd function can return it.
+///
+/// LLVM doesn't make sure the rest of the byte an `i1` is returned in is zero, so a `bool`
+/// can be passed to a JIT'd function but not returned from one.
+///
+/// This is unsafe to implement because nothing checks that the two agree.
+pub unsafe trait JitReturn<'a>: Compile<'a>
+{
+}
+
+macro_rules! jit_value(
+  ($($ty:ty),*) => ($(
+    unsafe impl<'a> JitParam<'a> for $ty {}
+    unsafe impl<'a> JitReturn<'a> for $ty {}
+  )*)
+);
+
+jit_value!{i8, u8, i16, u16, i32, u32, i64, u64, isize, usize, f32, f64}
+jit_value!{*const c_char, *const i16, *mut i16, *const u16, *mut u16, *const i32, *mut i32, *const u32, *mut u32,
+           *const i64, *mut i64, *const u64, *mut u64, *const f32, *mut f32, *const f64, *mut f64}
+unsafe impl<'a> JitParam<'a> for bool {}
+unsafe impl<'a> JitReturn<'a> for () {}
+
+/// A function pointer type that machine code compiled from an LLVM function can be used
+/// as, which is implemented for `extern "C" fn(A, B, ...) -> R` with up to seven
+/// `JitParam`s and a `JitReturn`.
+pub trait JitSignature<'a>: Copy
+{
+  /// Returns the LLVM types of the parameters, in order.
+  fn get_params(context: &'a Context) -> Vec<&'a Type>;
+
+  /// Returns the LLVM type of the return value.
+  fn get_return(context: &'a Context) -> &'a Type;
+
+  /// Make a function pointer of this type from the address of some machine code.
+  ///
+  /// This is unsafe because nothing checks that the code at `addr` has this signature.
+  unsafe fn from_addr(addr: *const u8) -> Self;
+
+  /// Returns the address of the machine code this points to.
+  fn to_addr(self) -> *const u8;
+}
+
+macro_rules! jit_signature(
+  ($($name:ident),*) => (
+    impl<'a, R, $($name),*> JitSignature<'a> for extern "C" fn($($name),*) -> R where R:JitReturn<'a>, $($name:JitParam<'a>),*
+    {
+      fn get_params(context: &'a Context) -> Vec<&'a Type>
+      {
+        vec![$($name::get_type(context)),*]
+      }
+
+      fn get_return(context: &'a Context) -> &'a Type
+      {
+        R::get_type(context)
+      }
+
+      unsafe fn from_addr(addr: *const u8) -> Self
+      {
+        mem::transmute(addr)
+      }
+
+      fn to_addr(self) -> *const u8
+      {
+        self as *const u8
+      }
+    }
+  )
+);
+
+jit_signature!{}
+jit_signature!{A}
+jit_signature!{A, B}
+jit_signature!{A, B, C}
+jit_signature!{A, B, C, D}
+jit_signature!{A, B, C, D, E}
+jit_signature!{A, B, C, D, E, F}
+jit_signature!{A, B, C, D, E, F, G}
+
+
+/// The way a function's signature differs from the signature it was expected to have, or
+/// why its machine code can't be used.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SignatureError
+{
+  /// The function isn't in any of the engine's modules.
+  NotInEngine,
+  /// The engine has no machine code for the function, like when it is a declaration that
+  /// nothing was found for.
+  NoAddress,
+  /// The function takes `found` parameters rather than `expected`.
+  ParamCount {
+    expected: usize,
+    found: usize
+  },
+  /// The parameter at `index` has the type `found` rather than `expected`.
+  Param {
+    index: usize,
+    expected: String,
+    found: String
+  },
+  /// The function returns `found` rather than `expected`.
+  Return {
+    expected: String,
+    found: String
+  }
+}
+
+impl fmt::Display for SignatureError
+{
+  fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result
+  {
+    match *self {
+      SignatureError::NotInEngine =>
+        write!(fmt, "the function isn't in any of the engine's modules"),
+      SignatureError::NoAddress =>
+        write!(fmt, "the engine has no machine code for the function"),
+      SignatureError::ParamCount { expected, found } =>
+        write!(fmt, "expected {} parameters, but the function takes {}", expected, found),
+      SignatureError::Param { index, ref expected, ref found } =>
+        write!(fmt, "expected parameter {} to be {}, but it is {}", index, expected, found),
+      SignatureError::Return { ref expected, ref found } =>
+        write!(fmt, "expected the function to return {}, but it returns {}", expected, found)
+    }
+  }
+}
+
+impl Error for SignatureError
+{
+  fn description(&self) -> &str
+  {
+    "function signature mismatch"
+  }
+}
+
+/// Check that `function` takes parameters of the types `params` and returns `ret`.
+pub fn check(function: &Function, params: &[&Type], ret: &Type) -> Result<(), SignatureError>
+{
+  let sig = function.get_signature();
+  let found = sig.get_params();
+  if found.len() != params.len() {
+    return Err(SignatureError::ParamCount {
+      expected: params.len(),
+      found: found.len()
+    })
+  }
+  for (index, (&expected, &found)) in params.iter().zip(found.iter()).enumerate() {
+    if expected != found {
+      return Err(SignatureError::Param {
+        index: index,
+        expected: format!("{}", expected),
+        found: format!("{}", found)
+      })
+    }
+  }
+  if ret != sig.get_return() {
+    return Err(SignatureError::Return {
+      expected: format!("{}", ret),
+      found: format!("{}", sig.get_return())
+    })
+  }
+  Ok(())
+}
+
+/// Check that `function` has the signature of the function pointer type `F`.
+pub fn check_jit<'a, F>(function: &'a Function) -> Result<(), SignatureError> where F: JitSignature<'a>
+{
+  let ctx = function.get_context();
+  check(function, &F::get_params(ctx), F::get_return(ctx))
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+
+use ffi::core;
+use ffi::execution_engine as engine;
+use ffi::prelude::LLVMValueRef;
+use libc::{self, c_void};
+
+use engine::JitEngine;
+use module::Module;
+use util;
+use value::Value;
+
+
+/// Registers Rust functions with a `SymbolResolver` under their own names.
+///
+/// ```rust
+/// #[macro_use] extern crate llvm;
+/// use llvm::*;
+/// extern "C" fn answer() -> u64 { 42 }
+/// # fn main() {
+/// let resolver = symbols![answer];
+/// assert!(resolver.is_defined("answer"));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! symbols(
+  ($($name:ident),*) => ({
+    let mut resolver = $crate::SymbolResolver::new();
+    $(resolver.define(stringify!($name), $name as *const _);)*
+    resolver
+  });
+  ($($name:ident),+,) => (symbols![$($name),+]);
+);
+
+
+/// Finds the addresses of the functions and globals that a module declares but doesn't
+/// define.
+///
+/// A name is looked up in the symbols defined on the resolver, then the libraries loaded
+/// into it in the order they were loaded, then the fallback, and finally in the running
+/// process if `search_process` was called.
+pub struct SymbolResolver
+{
+  symbols: HashMap<String, *const c_void>,
+  libraries: Vec<*mut c_void>,
+  fallback: Option<Box<dyn FnMut(&str) -> Option<*const c_void>>>,
+  search_process: bool
+}
+
+impl SymbolResolver
+{
+  /// Create a new resolver that doesn't find any symbols.
+  pub fn new() -> SymbolResolver
+  {
+    SymbolResolver {
+      symbols: HashMap::new(),
+      libraries: Vec::new(),
+      fallback: None,
+      search_process: false
+    }
+  }
+
+  /// Define the symbol `name` to be at `addr`, which should be a function or global with
+  /// the type the module declares it with.
+  pub fn define(&mut self, name: &str, addr: *const c_void) -> &mut SymbolResolver
+  {
+    self.symbols.insert(name.to_owned(), addr);
+    self
+  }
+
+  /// Returns true if the symbol `name` has been defined on this resolver.
+  pub fn is_defined(&self, name: &str) -> bool
+  {
+    self.symbols.contains_key(name)
+  }
+
+  /// Load the shared library at `path` so its symbols can be found, or return a
+  /// description of the error.
+  ///
+  /// The library is never unloaded, since code compiled with it may still call into it.
+  pub fn load_library(&mut self, path: &str) -> Result<(), String>
+  {
+    let handle = util::with_cstr(path, |path| unsafe { libc::dlopen(path, libc::RTLD_NOW | libc::RTLD_LOCAL) });
+    if handle.is_null() {
+      let message = unsafe { libc::dlerror() };
+      Err(if message.is_null() {
+        format!("failed to load {}", path)
+      } else {
+        unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned()
+      })
+    } else {
+      self.libraries.push(handle);
+      Ok(())
+    }
+  }
+
+  /// Set the function to call with the name of each symbol that isn't defined on this
+  /// resolver or in any of its libraries, which returns the address of it if it knows it.
+  pub fn set_fallback<F>(&mut self, fallback: F) where F: FnMut(&str) -> Option<*const c_void> + 'static
+  {
+    self.fallback = Some(Box::new(fallback));
+  }
+
+  /// Look up symbols that aren't found any other way in the running process, which lets
+  /// compiled code call anything the process has loaded, such as libc's functions.
+  pub fn search_process(&mut self) -> &mut SymbolResolver
+  {
+    self.search_process = true;
+    self
+  }
+
+  /// Returns the address of the symbol `name`, or `None` if it couldn't be found.
+  pub fn resolve(&mut self, name: &str) -> Option<*const c_void>
+  {
+    if let Some(&addr) = self.symbols.get(name) {
+      return Some(addr)
+    }
+    let found = util::with_cstr(name, |name| {
+      self.libraries.iter()
+        .map(|&library| unsafe { libc::dlsym(library, name) as *const c_void })
+        .find(|addr| !addr.is_null())
+    });
+    if found.is_some() {
+      return found
+    }
+    if let Some(ref mut fallback) = self.fallback {
+      if let Some(addr) = fallback(name) {
+        return Some(addr)
+      }
+    }
+    if !self.search_process {
+      return None
+    }
+    let addr = util::with_cstr(name, |name| unsafe { libc::dlsym(libc::RTLD_DEFAULT, name) });
+    if addr.is_null() {
+      None
+    } else {
+      Some(addr as *const c_void)
+    }
+  }
+}
+
+
+/// The symbols a module declares that a `SymbolResolver` couldn't find.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MissingSymbols
+{
+  names: Vec<String>
+}
+
+impl MissingSymbols
+{
+  /// Returns the names of the symbols that couldn't be found.
+  pub fn names(&self) -> &[String]
+  {
+    &self.names
+  }
+}
+
+impl fmt::Display for MissingSymbols
+{
+  fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result
+  {
+    write!(fmt, "unresolved symbols: {}", self.names.join(", "))
+  }
+}
+
+impl Error for MissingSymbols
+{
+  fn description(&self) -> &str
+  {
+    "unresolved symbols"
+  }
+}
+
+
+impl<'a> JitEngine<'a>
+{
+  /// Map every function and global that the modules in this engine declare without
+  /// defining to the address `resolver` finds for it, or return the names of those it
+  /// couldn't find.
+  ///
+  /// This must be done before any code in a module is compiled, which happens the first
+  /// time a function or global in it is looked up.
+  pub fn resolve_symbols(&self, resolver: &mut SymbolResolver) -> Result<(), MissingSymbols>
+  {
+    let mut missing = Vec::new();
+    for module in self.modules.borrow().modules() {
+      let module: &Module = module.into();
+      let functions = module.into_iter().map(|func| -> LLVMValueRef { func.into() });
+      let globals = module.global_values().map(|global| -> LLVMValueRef { global.into() });
+      for global in functions.chain(globals) {
+        let value: &Value = global.into();
+        let name = value.get_name().unwrap_or("");
+        if unsafe { core::LLVMIsDeclaration(global) } == 0 || name.starts_with("llvm.") {
+          continue
+        }
+        match resolver.resolve(name) {
+          Some(addr) => unsafe { engine::LLVMAddGlobalMapping(self.into(), global, addr as *mut c_void) },
+          None => missing.push(name.to_owned())
+        }
+      }
+    }
+    if missing.is_empty() {
+      Ok(())
+    } else {
+      Err(MissingSymbols {
+        names: missing
+      })
+    }
+  }
+}
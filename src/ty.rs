@@ -191,11 +191,16 @@ impl Type
     unsafe { target::LLVMABISizeOfType(target.into(), self.into()) as usize }
   }
   
-  /// Returns the element of this pointer type.
-  #[inline(always)]
+  /// Returns the element of this array or vector type, or `None` if it is neither.
+  ///
+  /// Pointers don't have element types, so this is `None` for them too.
   pub fn get_element(&self) -> Option<&Type> 
   {
-    unsafe { mem::transmute(core::LLVMGetElementType(self.into())) }
+    match self.kind() {
+      LLVMTypeKind::LLVMArrayTypeKind | LLVMTypeKind::LLVMVectorTypeKind | LLVMTypeKind::LLVMScalableVectorTypeKind =>
+        Some(unsafe { core::LLVMGetElementType(self.into()) }.into()),
+      _ => None
+    }
   }
 }
 
@@ -299,13 +304,9 @@ impl FunctionType
 
 impl CastFrom for FunctionType {
   type From = Type;
-  fn cast(mut ty: &Type) -> Option<&FunctionType> {
+  fn cast(ty: &Type) -> Option<&FunctionType> {
     unsafe {
       use libc::c_uint;
-      while let Some(elem) = ty.get_element() {
-	      ty = elem;
-      }
-      
       let kind = core::LLVMGetTypeKind(ty.into());
       if kind as c_uint == LLVMTypeKind::LLVMFunctionTypeKind as c_uint {
       	mem::transmute(ty)
@@ -1,12 +1,14 @@
+use std::collections::HashSet;
 use std::ffi::CString;
 use std::{fmt, mem, ptr};
 use std::ops::{Deref, Index};
 
-use libc::{c_char, c_int, c_uint};
+use libc::{c_char, c_int, c_uint, c_ulonglong};
 use ffi::core;
-use ffi::prelude::LLVMValueRef;
-use ffi::LLVMAttribute;
+use ffi::prelude::{LLVMAttributeRef, LLVMBasicBlockRef, LLVMBuilderRef, LLVMUseRef, LLVMValueRef};
+use ffi::{LLVMAttributeFunctionIndex, LLVMAttributeIndex, LLVMIntPredicate, LLVMRealPredicate, LLVMTypeKind, LLVMValue};
 use ffi::core::{
+	LLVMConstArray,
 	LLVMConstStringInContext,
 	LLVMConstStructInContext,
 	LLVMConstVector,
@@ -14,17 +16,12 @@ use ffi::core::{
 	LLVMGetValueName,
 	LLVMSetValueName,
 	
-	LLVMGetElementType,
 	LLVMGetEntryBasicBlock,
 	LLVMAppendBasicBlockInContext,
 	
-	LLVMAddAttribute,
-	LLVMGetAttribute,	
-	LLVMRemoveAttribute,
-  
-  LLVMAddFunctionAttr,
-  LLVMGetFunctionAttr,
-  LLVMRemoveFunctionAttr,
+  LLVMAddAttributeAtIndex,
+  LLVMGetEnumAttributeAtIndex,
+  LLVMRemoveEnumAttributeAtIndex,
   
   LLVMGetParam,
   LLVMCountParams,
@@ -35,14 +32,18 @@ use ffi::core::{
   LLVMSetInitializer,
   
   LLVMIsAGlobalValue,
+  LLVMIsConstant,
+  LLVMIsNull,
+  LLVMIsUndef,
   LLVMGetUndef,
   LLVMTypeOf,
 };
 
-use block::BasicBlock;
+use block::{BasicBlock, BlockIter};
 use context::{Context, GetContext};
 use util::{self, CastFrom};
 use ty::{FunctionType, Type};
+use verify::{self, VerifierError};
 
 /// A typed value that can be used as an operand in instructions.
 pub struct Value;
@@ -72,6 +73,16 @@ impl Value
     }.into()
   }
   
+  /// Create a new constant array with the element type and values given.
+  pub fn new_array<'a>(elem: &'a Type, vals: &[&'a Value]) -> &'a Value 
+  {
+    unsafe { 
+    	LLVMConstArray(elem.into(), 
+    		             vals.as_ptr() as *mut LLVMValueRef, 
+    		             vals.len() as c_uint)
+    }.into()
+  }
+  
   /// Create a new constant C string from the text given.
   pub fn new_string<'a>(context: &'a Context, 
   	                    text: &str, 
@@ -113,6 +124,303 @@ impl Value
   {
     unsafe { LLVMTypeOf(self.into()) }.into()
   }
+  
+  /// Iterate through the uses of this value, each of which is an operand of another value.
+  pub fn uses(&self) -> Uses 
+  {
+    Uses {
+      cur: unsafe { core::LLVMGetFirstUse(self.into()) },
+      marker: ::std::marker::PhantomData
+    }
+  }
+  
+  /// Iterate through the values that use this value as an operand.
+  ///
+  /// A value that uses this value more than once is yielded once per use.
+  pub fn users(&self) -> Users 
+  {
+    Users { uses: self.uses() }
+  }
+  
+  /// Returns true if nothing uses this value.
+  pub fn is_unused(&self) -> bool 
+  {
+    self.uses().next().is_none()
+  }
+  
+  /// Replace every use of this value with `other`, which must have the same type.
+  pub fn replace_all_uses_with(&self, other: &Value) 
+  {
+    assert_eq!(self.get_type(), other.get_type());
+    unsafe { core::LLVMReplaceAllUsesWith(self.into(), other.into()) }
+  }
+  
+  /// Returns true if this value is a constant.
+  pub fn is_constant(&self) -> bool 
+  {
+    unsafe { LLVMIsConstant(self.into()) != 0 }
+  }
+  
+  /// Returns true if this value is the null value of its type.
+  pub fn is_null(&self) -> bool 
+  {
+    unsafe { LLVMIsNull(self.into()) != 0 }
+  }
+  
+  /// Returns true if this value is undefined.
+  pub fn is_undef(&self) -> bool 
+  {
+    unsafe { LLVMIsUndef(self.into()) != 0 }
+  }
+}
+
+
+/// A name for the instructions that are folded into constants, which never get inserted.
+static NULL_NAME:[c_char; 1] = [0];
+
+macro_rules! const_unary_op (
+  ($name:ident, $func:ident) => (
+    pub fn $name<'a>(value: &'a Value) -> Option<&'a Value> 
+    {
+      fold(value.get_context(), |builder| unsafe { 
+        core::$func(builder, value.into(), NULL_NAME.as_ptr()) 
+      })
+    }
+  );
+  ($name:ident, $ifunc:ident, $ffunc:ident) => (
+    pub fn $name<'a>(value: &'a Value) -> Option<&'a Value> 
+    {
+      let scalar = scalar_type(value.get_type());
+      let func = if scalar.is_integer() {
+        core::$ifunc
+      } else if scalar.is_float() {
+        core::$ffunc
+      } else {
+        return None
+      };
+      fold(value.get_context(), |builder| unsafe { 
+        func(builder, value.into(), NULL_NAME.as_ptr()) 
+      })
+    }
+  );
+);
+
+macro_rules! const_bin_op (
+  ($name:ident, $func:ident) => (
+    pub fn $name<'a>(left: &'a Value, right: &'a Value) -> Option<&'a Value> 
+    {
+      fold(left.get_context(), |builder| unsafe { 
+        core::$func(builder, left.into(), right.into(), NULL_NAME.as_ptr()) 
+      })
+    }
+  );
+  ($name:ident, $ifunc:ident, $ffunc:ident) => (
+    pub fn $name<'a>(left: &'a Value, right: &'a Value) -> Option<&'a Value> 
+    {
+      let scalar = scalar_type(left.get_type());
+      let func = if scalar.is_integer() {
+        core::$ifunc
+      } else if scalar.is_float() {
+        core::$ffunc
+      } else {
+        return None
+      };
+      fold(left.get_context(), |builder| unsafe { 
+        func(builder, left.into(), right.into(), NULL_NAME.as_ptr()) 
+      })
+    }
+  );
+);
+
+macro_rules! const_cast_op (
+  ($name:ident, $func:ident) => (
+    pub fn $name<'a>(value: &'a Value, dest: &'a Type) -> Option<&'a Value> 
+    {
+      fold(value.get_context(), |builder| unsafe { 
+        core::$func(builder, value.into(), dest.into(), NULL_NAME.as_ptr()) 
+      })
+    }
+  );
+);
+
+/// Returns the type of the elements of `ty` if it is a vector, or `ty` otherwise.
+fn scalar_type(ty: &Type) -> &Type 
+{
+  match ty.kind() {
+    LLVMTypeKind::LLVMVectorTypeKind | LLVMTypeKind::LLVMScalableVectorTypeKind =>
+      unsafe { core::LLVMGetElementType(ty.into()) }.into(),
+    _ => ty
+  }
+}
+
+/// Fold the instruction `build` makes into a constant, or return `None` if LLVM can't.
+///
+/// The builder `build` is given isn't positioned in any block, and folds the instruction
+/// when all of its operands are constants, so nothing is ever inserted.
+fn fold<'a, F>(context: &'a Context, build: F) -> Option<&'a Value> where F: FnOnce(LLVMBuilderRef) -> LLVMValueRef
+{
+  unsafe {
+    let builder = core::LLVMCreateBuilderInContext(context.into());
+    let value = build(builder);
+    core::LLVMDisposeBuilder(builder);
+    if LLVMIsConstant(value) != 0 {
+      Some(value.into())
+    } else {
+      core::LLVMDeleteInstruction(value);
+      None
+    }
+  }
+}
+
+/// Constant construction and folding.
+///
+/// These never emit instructions: each of them either folds its operands into a new
+/// constant, which may be a constant expression, or returns `None` when LLVM can't.
+impl Value 
+{
+  /// Create the null value of the type given, such as zero or a null pointer.
+  pub fn null<'a>(ty: &'a Type) -> &'a Value 
+  {
+    unsafe { core::LLVMConstNull(ty.into()) }.into()
+  }
+  
+  /// Create the value of the integer or vector type given with every bit set.
+  pub fn all_ones<'a>(ty: &'a Type) -> &'a Value 
+  {
+    unsafe { core::LLVMConstAllOnes(ty.into()) }.into()
+  }
+  
+  /// Create a constant integer of the type given.
+  ///
+  /// If `signed` is true, `value` is sign-extended to the width of `ty`.
+  pub fn const_int<'a>(ty: &'a Type, value: u64, signed: bool) -> &'a Value 
+  {
+    unsafe { core::LLVMConstInt(ty.into(), value as c_ulonglong, signed as c_int) }.into()
+  }
+  
+  /// Create a constant integer of the type given from 64-bit words, least
+  /// significant word first.
+  pub fn const_int_words<'a>(ty: &'a Type, words: &[u64]) -> &'a Value 
+  {
+    unsafe { 
+      core::LLVMConstIntOfArbitraryPrecision(ty.into(), 
+                                             words.len() as c_uint, 
+                                             words.as_ptr()) 
+    }.into()
+  }
+  
+  /// Create a constant floating-point number of the type given.
+  pub fn const_real<'a>(ty: &'a Type, value: f64) -> &'a Value 
+  {
+    unsafe { core::LLVMConstReal(ty.into(), value) }.into()
+  }
+  
+  /// Create a constant floating-point number of the type given by parsing `text`.
+  ///
+  /// This keeps the full precision of the text, unlike `const_real`.
+  pub fn const_real_from_str<'a>(ty: &'a Type, text: &str) -> &'a Value 
+  {
+    unsafe { 
+      core::LLVMConstRealOfStringAndSize(ty.into(), 
+                                         text.as_ptr() as *const c_char, 
+                                         text.len() as c_uint) 
+    }.into()
+  }
+  
+  const_unary_op!{const_neg, LLVMBuildNeg, LLVMBuildFNeg}
+  const_unary_op!{const_not, LLVMBuildNot}
+  
+  const_bin_op!{const_add, LLVMBuildAdd, LLVMBuildFAdd}
+  const_bin_op!{const_sub, LLVMBuildSub, LLVMBuildFSub}
+  const_bin_op!{const_mul, LLVMBuildMul, LLVMBuildFMul}
+  const_bin_op!{const_div, LLVMBuildSDiv, LLVMBuildFDiv}
+  const_bin_op!{const_udiv, LLVMBuildUDiv}
+  const_bin_op!{const_rem, LLVMBuildSRem, LLVMBuildFRem}
+  const_bin_op!{const_urem, LLVMBuildURem}
+  const_bin_op!{const_shl, LLVMBuildShl}
+  const_bin_op!{const_ashr, LLVMBuildAShr}
+  const_bin_op!{const_lshr, LLVMBuildLShr}
+  const_bin_op!{const_and, LLVMBuildAnd}
+  const_bin_op!{const_or, LLVMBuildOr}
+  const_bin_op!{const_xor, LLVMBuildXor}
+  
+  const_cast_op!{const_trunc, LLVMBuildTrunc}
+  const_cast_op!{const_sext, LLVMBuildSExt}
+  const_cast_op!{const_zext, LLVMBuildZExt}
+  const_cast_op!{const_fp_trunc, LLVMBuildFPTrunc}
+  const_cast_op!{const_fp_ext, LLVMBuildFPExt}
+  const_cast_op!{const_ui_to_fp, LLVMBuildUIToFP}
+  const_cast_op!{const_si_to_fp, LLVMBuildSIToFP}
+  const_cast_op!{const_fp_to_ui, LLVMBuildFPToUI}
+  const_cast_op!{const_fp_to_si, LLVMBuildFPToSI}
+  const_cast_op!{const_ptr_to_int, LLVMBuildPtrToInt}
+  const_cast_op!{const_int_to_ptr, LLVMBuildIntToPtr}
+  const_cast_op!{const_bit_cast, LLVMBuildBitCast}
+  
+  /// Compare two constants with the predicate given, treating integers as signed.
+  pub fn const_cmp<'a>(left: &'a Value, right: &'a Value, pred: Predicate) -> Option<&'a Value> 
+  {
+    Value::const_cmp_internal(left, right, pred, true)
+  }
+  
+  /// Compare two constants with the predicate given, treating integers as unsigned.
+  pub fn const_ucmp<'a>(left: &'a Value, right: &'a Value, pred: Predicate) -> Option<&'a Value> 
+  {
+    Value::const_cmp_internal(left, right, pred, false)
+  }
+  
+  fn const_cmp_internal<'a>(left: &'a Value, right: &'a Value, 
+                            pred: Predicate, signed: bool) -> Option<&'a Value> 
+  {
+    let ty = left.get_type();
+    assert_eq!(ty, right.get_type());
+    
+    // Pointers are compared by address, like integers.
+    let scalar = scalar_type(ty);
+    if scalar.is_integer() || scalar.is_pointer() {
+      fold(left.get_context(), |builder| unsafe { 
+        core::LLVMBuildICmp(builder, pred.to_int(signed), left.into(), right.into(), NULL_NAME.as_ptr()) 
+      })
+    } else if scalar.is_float() {
+      fold(left.get_context(), |builder| unsafe { 
+        core::LLVMBuildFCmp(builder, pred.to_real(), left.into(), right.into(), NULL_NAME.as_ptr()) 
+      })
+    } else {
+      None
+    }
+  }
+  
+  /// Yield `true_val` if the constant `cond` is `1`, and `false_val` otherwise.
+  pub fn const_select<'a>(cond: &'a Value, true_val: &'a Value, false_val: &'a Value) -> Option<&'a Value> 
+  {
+    fold(cond.get_context(), |builder| unsafe { 
+      core::LLVMBuildSelect(builder, cond.into(), true_val.into(), false_val.into(), NULL_NAME.as_ptr()) 
+    })
+  }
+  
+  /// Compute the address of a subelement of a constant aggregate of the type `ty` that
+  /// `pointer` points to.
+  ///
+  /// If `inbounds` is true, the address must be inside the aggregate, or the result is a
+  /// poison value.
+  pub fn const_gep<'a>(ty: &'a Type, pointer: &'a Value, indices: &[&'a Value], inbounds: bool) -> &'a Value 
+  {
+    let func = if inbounds { core::LLVMConstInBoundsGEP2 } else { core::LLVMConstGEP2 };
+    unsafe { 
+      func(ty.into(), 
+           pointer.into(), 
+           indices.as_ptr() as *mut LLVMValueRef, 
+           indices.len() as c_uint) 
+    }.into()
+  }
+  
+  /// Extract the element at `index` from a constant aggregate.
+  pub fn const_extract_value<'a>(agg: &'a Value, index: usize) -> Option<&'a Value> 
+  {
+    fold(agg.get_context(), |builder| unsafe { 
+      core::LLVMBuildExtractValue(builder, agg.into(), index as c_uint, NULL_NAME.as_ptr()) 
+    })
+  }
 }
 
 
@@ -152,7 +460,7 @@ impl CastFrom for GlobalValue
 
 
 /// Comparative operations on values.
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum Predicate 
 {
   Equal,
@@ -163,6 +471,52 @@ pub enum Predicate
   LessThanOrEqual
 }
 
+impl Predicate 
+{
+  /// Returns the LLVM integer predicate, with signed or unsigned ordering.
+  pub(crate) fn to_int(self, signed: bool) -> LLVMIntPredicate 
+  {
+    match (self, signed) {
+      (Predicate::Equal, _)                  => LLVMIntPredicate::LLVMIntEQ,
+      (Predicate::NotEqual, _)               => LLVMIntPredicate::LLVMIntNE,
+      (Predicate::LessThan, true)            => LLVMIntPredicate::LLVMIntSLT,
+      (Predicate::LessThan, false)           => LLVMIntPredicate::LLVMIntULT,
+      (Predicate::LessThanOrEqual, true)     => LLVMIntPredicate::LLVMIntSLE,
+      (Predicate::LessThanOrEqual, false)    => LLVMIntPredicate::LLVMIntULE,
+      (Predicate::GreaterThan, true)         => LLVMIntPredicate::LLVMIntSGT,
+      (Predicate::GreaterThan, false)        => LLVMIntPredicate::LLVMIntUGT,
+      (Predicate::GreaterThanOrEqual, true)  => LLVMIntPredicate::LLVMIntSGE,
+      (Predicate::GreaterThanOrEqual, false) => LLVMIntPredicate::LLVMIntUGE,
+    }
+  }
+  
+  /// Returns the comparison an LLVM integer predicate makes, regardless of signedness.
+  pub(crate) fn from_int(pred: LLVMIntPredicate) -> Predicate 
+  {
+    match pred {
+      LLVMIntPredicate::LLVMIntEQ                               => Predicate::Equal,
+      LLVMIntPredicate::LLVMIntNE                               => Predicate::NotEqual,
+      LLVMIntPredicate::LLVMIntSLT | LLVMIntPredicate::LLVMIntULT => Predicate::LessThan,
+      LLVMIntPredicate::LLVMIntSLE | LLVMIntPredicate::LLVMIntULE => Predicate::LessThanOrEqual,
+      LLVMIntPredicate::LLVMIntSGT | LLVMIntPredicate::LLVMIntUGT => Predicate::GreaterThan,
+      LLVMIntPredicate::LLVMIntSGE | LLVMIntPredicate::LLVMIntUGE => Predicate::GreaterThanOrEqual,
+    }
+  }
+  
+  /// Returns the ordered LLVM floating-point predicate.
+  pub(crate) fn to_real(self) -> LLVMRealPredicate 
+  {
+    match self {
+      Predicate::Equal              => LLVMRealPredicate::LLVMRealOEQ,
+      Predicate::NotEqual           => LLVMRealPredicate::LLVMRealONE,
+      Predicate::GreaterThan        => LLVMRealPredicate::LLVMRealOGT,
+      Predicate::GreaterThanOrEqual => LLVMRealPredicate::LLVMRealOGE,
+      Predicate::LessThan           => LLVMRealPredicate::LLVMRealOLT,
+      Predicate::LessThanOrEqual    => LLVMRealPredicate::LLVMRealOLE
+    }
+  }
+}
+
 
 /// A function argument.
 pub struct Arg;
@@ -180,52 +534,52 @@ impl Deref for Arg
 
 impl Arg 
 {
+  /// Returns the function this is an argument of and the index of its attributes.
+  fn attribute_index(&self) -> (LLVMValueRef, LLVMAttributeIndex)
+  {
+    unsafe {
+      let func = core::LLVMGetParamParent(self.into());
+      let mut index = 1;
+      let mut param = LLVMGetFirstParam(func);
+      while param != self.into() {
+        param = LLVMGetNextParam(param);
+        index += 1;
+      }
+      (func, index)
+    }
+  }
+  
   /// Add the attribute given to this argument.
   pub fn add_attribute(&self, attr: Attribute) 
   {
-    unsafe { LLVMAddAttribute(self.into(), attr.into()) }
+    self.add_attributes(&[attr])
   }
   
   /// Add all the attributes given to this argument.
   pub fn add_attributes(&self, attrs: &[Attribute]) 
   {
-    let mut sum = LLVMAttribute::empty();
-    for attr in attrs {
-      let attr:LLVMAttribute = (*attr).into();
-      sum = sum | attr;
-    }
-    unsafe { LLVMAddAttribute(self.into(), sum.into()) }
+    let (func, index) = self.attribute_index();
+    add_attributes_at(self.get_context(), func, index, attrs)
   }
   
   /// Returns true if this argument has the attribute given.
   pub fn has_attribute(&self, attr: Attribute) -> bool 
   {
-    unsafe {
-      let other = LLVMGetAttribute(self.into());
-      other.contains(attr.into())
-    }
+    self.has_attributes(&[attr])
   }
   
   /// Returns true if this argument has all the attributes given.
   pub fn has_attributes(&self, attrs: &[Attribute]) -> bool 
   {
-    unsafe {
-      let other = LLVMGetAttribute(self.into());
-      for &attr in attrs {
-        if !other.contains(attr.into()) {
-            return false;
-        }
-      }
-      return true;
-    }
+    let (func, index) = self.attribute_index();
+    has_attributes_at(func, index, attrs)
   }
   
   /// Remove an attribute from this argument.
   pub fn remove_attribute(&self, attr: Attribute) 
   {
-    unsafe { 
-    	LLVMRemoveAttribute(self.into(), attr.into())
-    }
+    let (func, index) = self.attribute_index();
+    remove_attribute_at(func, index, attr)
   }
 }
 
@@ -266,17 +620,13 @@ impl CastFrom for Function
   
   fn cast<'a>(val: &'a Value) -> Option<&'a Function> 
   {
-    let ty = val.get_type();
-    let mut is_func = ty.is_function();
-    
-    if let Some(elem) = ty.get_element() {
-      is_func = is_func || elem.is_function()
-    }
-    
-    if is_func {
-      Some(unsafe { mem::transmute(val) })
-    } else {
-      None
+    // A function's type is just a pointer, so the value itself has to be checked.
+    unsafe {
+      if core::LLVMIsAFunction(val.into()).is_null() {
+        None
+      } else {
+        Some(mem::transmute(val))
+      }
     }
   }
 }
@@ -291,6 +641,43 @@ impl Function
     })
   }
   
+  /// Iterate through the basic blocks of this function in order.
+  pub fn basic_blocks(&self) -> BlockIter 
+  {
+    BlockIter::new(
+      unsafe { core::LLVMGetFirstBasicBlock(self.into()) },
+      unsafe { core::LLVMGetLastBasicBlock(self.into()) })
+  }
+  
+  /// Returns the number of basic blocks in this function.
+  pub fn num_basic_blocks(&self) -> usize 
+  {
+    unsafe { core::LLVMCountBasicBlocks(self.into()) as usize }
+  }
+  
+  /// Returns the blocks of this function that can be reached from its entry block.
+  fn reachable_blocks(&self) -> HashSet<LLVMBasicBlockRef> 
+  {
+    let mut reached = HashSet::new();
+    let mut stack: Vec<&BasicBlock> = self.get_entry().into_iter().collect();
+    while let Some(block) = stack.pop() {
+      if reached.insert(block.into()) {
+        stack.extend(block.successors());
+      }
+    }
+    reached
+  }
+  
+  /// Returns the blocks of this function that can't be reached from its entry block.
+  pub fn unreachable_blocks(&self) -> Vec<&BasicBlock> 
+  {
+    let reached = self.reachable_blocks();
+    self.basic_blocks().filter(|&block| {
+      let ptr: LLVMBasicBlockRef = block.into();
+      !reached.contains(&ptr)
+    }).collect()
+  }
+  
   /// Returns the entry block of this function or `None` if there is none.
   pub fn get_entry(&self) -> Option<&BasicBlock> 
   {
@@ -306,12 +693,18 @@ impl Function
     }
   }
   
+  /// Verify that the body of this function is valid, returning the problems the verifier
+  /// found when an error occurs.
+  pub fn verify(&self) -> Result<(), VerifierError> 
+  {
+    verify::verify_function(self)
+  }
+  
   /// Returns the function signature representing this function's signature.
   pub fn get_signature(&self) -> &FunctionType
   {
     unsafe {
-      let ty = LLVMTypeOf(self.into());
-      LLVMGetElementType(ty).into()
+      core::LLVMGlobalGetValueType(self.into()).into()
     }
   }
   
@@ -326,51 +719,31 @@ impl Function
   /// Add the attribute given to this function.
   pub fn add_attribute(&self, attr: Attribute) 
   {
-    unsafe { LLVMAddFunctionAttr(self.into(), attr.into()) }
+    self.add_attributes(&[attr])
   }
   
   /// Add all the attributes given to this function.
   pub fn add_attributes(&self, attrs: &[Attribute]) 
   {
-    let mut sum = LLVMAttribute::empty();
-    
-    for attr in attrs {
-        let attr:LLVMAttribute = (*attr).into();
-        sum = sum | attr;
-    }
-    
-    unsafe { LLVMAddFunctionAttr(self.into(), sum.into()) }
+    add_attributes_at(self.get_context(), self.into(), LLVMAttributeFunctionIndex, attrs)
   }
   
   /// Returns true if the attribute given is set in this function.
   pub fn has_attribute(&self, attr: Attribute) -> bool 
   {
-    unsafe {
-      let other = LLVMGetFunctionAttr(self.into());
-      other.contains(attr.into())
-    }
+    self.has_attributes(&[attr])
   }
   
   /// Returns true if all the attributes given is set in this function.
   pub fn has_attributes(&self, attrs: &[Attribute]) -> bool 
   {
-    unsafe {
-      let other = LLVMGetFunctionAttr(self.into());
-      
-      for &attr in attrs {
-          if !other.contains(attr.into()) {
-              return false;
-          }
-      }
-      
-      return true;
-    }
+    has_attributes_at(self.into(), LLVMAttributeFunctionIndex, attrs)
   }
   
   /// Remove the attribute given from this function.
   pub fn remove_attribute(&self, attr: Attribute) 
   {
-    unsafe { LLVMRemoveFunctionAttr(self.into(), attr.into()) }
+    remove_attribute_at(self.into(), LLVMAttributeFunctionIndex, attr)
   }
 }
 
@@ -382,9 +755,11 @@ impl<'a> IntoIterator for &'a Function
   /// Iterate through the functions in the module
   fn into_iter(self) -> ValueIter<'a, &'a Arg> 
   {    
- 		ValueIter::new(
+ 		ValueIter::new_double_ended(
  			unsafe { LLVMGetFirstParam(self.into()) },
- 			LLVMGetNextParam)  	
+ 			unsafe { core::LLVMGetLastParam(self.into()) },
+ 			LLVMGetNextParam,
+ 			core::LLVMGetPreviousParam)  	
   }
 }
 
@@ -410,19 +785,19 @@ pub enum Attribute
   NoReturn =          0b100,
   /// Force argument to be passed in register.
   InReg =             0b1000,
-  /// Hidden pointer to structure to return.
+  /// Hidden pointer to structure to return, which is added with the type `i8`.
   StructRet =         0b10000,
   /// Function doesn't unwind stack.
   NoUnwind =          0b100000,
   /// Consider to not alias after call.
   NoAlias =           0b1000000,
-  /// Pass structure by value.
+  /// Pass structure by value, which is added with the type `i8`.
   ByVal =             0b10000000,
   /// Nested function static chain.
   Nest =              0b100000000,
-  /// Function doesn't access memory.
+  /// Argument isn't used to access memory.
   ReadNone =          0b1000000000,
-  /// Function only reads from memory.
+  /// Argument is only used to read from memory.
   ReadOnly =          0b10000000000,
   /// Never inline this function.
   NoInline =          0b100000000000,
@@ -434,7 +809,7 @@ pub enum Attribute
   StackProtect =      0b100000000000000,
   /// Stack protection required.
   StackProtectReq =   0b1000000000000000,
-  /// Alignment of parameter (5 bits) stored as log2 of alignment with +1 bias 0 means unaligned (different from align(1)).
+  /// Alignment of parameter, which is added as `align 1`.
   Alignment =         0b10000000000000000,
   /// Function creates no aliases of pointer.
   NoCapture =         0b100000000000000000,
@@ -446,7 +821,7 @@ pub enum Attribute
   Naked =             0b100000000000000000000,
   /// The source language has marked this function as inline.
   InlineHint =        0b1000000000000000000000,
-  /// Alignment of stack for function (3 bits) stored as log2 of alignment with +1 bias 0 means unaligned (different from alignstack=(1)).
+  /// Alignment of stack for function, which is added as `alignstack(1)`.
   StackAlignment =    0b11100000000000000000000000000,
   /// This function returns twice.
   ReturnsTwice =      0b100000000000000000000000000000,
@@ -456,22 +831,85 @@ pub enum Attribute
   NonLazyBind =       0b10000000000000000000000000000000
 }
 
-impl From<LLVMAttribute> for Attribute 
+impl Attribute
 {
-  fn from(attr: LLVMAttribute) -> Attribute 
+  /// Returns the name LLVM gives this attribute and the value it is added with.
+  fn llvm_name(self) -> (&'static str, u64)
+  {
+    match self {
+      Attribute::ZExt => ("zeroext", 0),
+      Attribute::SExt => ("signext", 0),
+      Attribute::NoReturn => ("noreturn", 0),
+      Attribute::InReg => ("inreg", 0),
+      Attribute::StructRet => ("sret", 0),
+      Attribute::NoUnwind => ("nounwind", 0),
+      Attribute::NoAlias => ("noalias", 0),
+      Attribute::ByVal => ("byval", 0),
+      Attribute::Nest => ("nest", 0),
+      Attribute::ReadNone => ("readnone", 0),
+      Attribute::ReadOnly => ("readonly", 0),
+      Attribute::NoInline => ("noinline", 0),
+      Attribute::AlwaysInline => ("alwaysinline", 0),
+      Attribute::OptimizeForSize => ("optsize", 0),
+      Attribute::StackProtect => ("ssp", 0),
+      Attribute::StackProtectReq => ("sspreq", 0),
+      Attribute::Alignment => ("align", 1),
+      // `captures(none)`, which is encoded as zero.
+      Attribute::NoCapture => ("captures", 0),
+      Attribute::NoRedZone => ("noredzone", 0),
+      Attribute::NoImplicitFloat => ("noimplicitfloat", 0),
+      Attribute::Naked => ("naked", 0),
+      Attribute::InlineHint => ("inlinehint", 0),
+      Attribute::StackAlignment => ("alignstack", 1),
+      Attribute::ReturnsTwice => ("returns_twice", 0),
+      // `uwtable(async)`, the kind of unwind table LLVM emits by default.
+      Attribute::UWTable => ("uwtable", 2),
+      Attribute::NonLazyBind => ("nonlazybind", 0)
+    }
+  }
+  
+  /// Returns the kind LLVM identifies this attribute by.
+  pub(crate) fn kind(self) -> c_uint
   {
-    unsafe { mem::transmute(attr) }
+    let (name, _) = self.llvm_name();
+    unsafe { core::LLVMGetEnumAttributeKindForName(name.as_ptr() as *const c_char, name.len()) }
+  }
+  
+  /// Make this attribute in the context given.
+  fn to_native(self, context: &Context) -> LLVMAttributeRef
+  {
+    let (_, value) = self.llvm_name();
+    unsafe {
+      match self {
+        Attribute::StructRet | Attribute::ByVal =>
+          core::LLVMCreateTypeAttribute(context.into(), self.kind(), Type::get::<i8>(context).into()),
+        _ =>
+          core::LLVMCreateEnumAttribute(context.into(), self.kind(), value)
+      }
+    }
   }
 }
 
-impl From<Attribute> for LLVMAttribute 
+/// Add `attrs` to the attributes of `func` at `index`.
+fn add_attributes_at(context: &Context, func: LLVMValueRef, index: LLVMAttributeIndex, attrs: &[Attribute])
 {
-  fn from(attr: Attribute) -> LLVMAttribute 
-  {
-    unsafe { mem::transmute(attr) }
+  for &attr in attrs {
+    unsafe { LLVMAddAttributeAtIndex(func, index, attr.to_native(context)) }
   }
 }
 
+/// Returns true if the attributes of `func` at `index` include all of `attrs`.
+fn has_attributes_at(func: LLVMValueRef, index: LLVMAttributeIndex, attrs: &[Attribute]) -> bool
+{
+  attrs.iter().all(|attr| unsafe { !LLVMGetEnumAttributeAtIndex(func, index, attr.kind()).is_null() })
+}
+
+/// Remove `attr` from the attributes of `func` at `index`.
+fn remove_attribute_at(func: LLVMValueRef, index: LLVMAttributeIndex, attr: Attribute)
+{
+  unsafe { LLVMRemoveEnumAttributeAtIndex(func, index, attr.kind()) }
+}
+
 impl GetContext for Value 
 {
   fn get_context(&self) -> &Context 
@@ -481,25 +919,137 @@ impl GetContext for Value
 }
 
 
+/// The logic shared by the iterators through LLVM's linked lists of values and blocks,
+/// which go from `cur` to `last` inclusively.
+///
+/// `last` is null when only the start of the list is known, and is found by walking
+/// forwards from `cur` the first time it is needed.
+pub(crate) struct Links<T>
+{
+  cur      : *mut T,
+  last     : *mut T,
+  step     : unsafe extern "C" fn(*mut T) -> *mut T,
+  step_back: Option<unsafe extern "C" fn(*mut T) -> *mut T>
+}
+
+impl<T> Clone for Links<T>
+{
+  fn clone(&self) -> Links<T>
+  {
+    *self
+  }
+}
+
+impl<T> Copy for Links<T> {}
+
+impl<T> Links<T>
+{
+  pub(crate) fn new(cur: *mut T, 
+                    last: *mut T,
+                    step: unsafe extern "C" fn(*mut T) -> *mut T,
+                    step_back: Option<unsafe extern "C" fn(*mut T) -> *mut T>) -> Links<T>
+  {
+    Links {
+      cur: cur,
+      last: last,
+      step: step,
+      step_back: step_back
+    }
+  }
+  
+  /// Returns the link after `link`, or the first one after the end of the list.
+  fn after(&self, link: *mut T) -> *mut T
+  {
+    unsafe { (self.step)(link) }
+  }
+  
+  pub(crate) fn next(&mut self) -> Option<*mut T>
+  {
+    let old = self.cur;
+    
+    if !old.is_null() {
+      if old == self.last {
+        self.cur = ptr::null_mut();
+        self.last = ptr::null_mut();
+      } else {
+        self.cur = self.after(old);
+      }
+      Some(old)
+    } else {
+      None
+    }
+  }
+  
+  pub(crate) fn next_back(&mut self) -> Option<*mut T>
+  {
+    if self.cur.is_null() {
+      return None
+    }
+    if self.last.is_null() {
+      let mut last = self.cur;
+      while !self.after(last).is_null() {
+        last = self.after(last);
+      }
+      self.last = last;
+    }
+    let old = self.last;
+    
+    if old == self.cur {
+      self.cur = ptr::null_mut();
+      self.last = ptr::null_mut();
+    } else {
+      self.last = match self.step_back {
+        Some(step_back) => unsafe { step_back(old) },
+        None => {
+          let mut prev = self.cur;
+          while self.after(prev) != old {
+            prev = self.after(prev);
+          }
+          prev
+        }
+      };
+    }
+    Some(old)
+  }
+}
+
+
 /// Value Iterator implementation.
 ///
 /// T can be all descendent types of LLVMValueRef.  
 #[derive(Copy, Clone)]
 pub struct ValueIter<'a, T: From<LLVMValueRef>> {
-  cur    : LLVMValueRef,
-  step   : unsafe extern "C" fn(LLVMValueRef) -> LLVMValueRef,
+  links  : Links<LLVMValue>,
   marker1: ::std::marker::PhantomData<&'a ()>,
   marker2: ::std::marker::PhantomData<T>,
 }
 
 impl<'a, T: From<LLVMValueRef>> ValueIter<'a, T>
 {
+	/// Iterate from `cur` until `step` returns null.
+	///
+	/// Iterating backwards through this has to walk forwards from `cur` to find each
+	/// value, so use `new_double_ended` when there is a way to step back.
 	pub fn new(cur: LLVMValueRef, 
 		         step: unsafe extern "C" fn(LLVMValueRef) -> LLVMValueRef) -> Self
+	{
+		ValueIter::from_links(Links::new(cur, ptr::null_mut(), step, None))
+	}
+	
+	/// Iterate from `cur` to `last` inclusively, using `step` to go forwards and
+	/// `step_back` to go backwards.
+	pub fn new_double_ended(cur: LLVMValueRef, 
+		                      last: LLVMValueRef,
+		                      step: unsafe extern "C" fn(LLVMValueRef) -> LLVMValueRef,
+		                      step_back: unsafe extern "C" fn(LLVMValueRef) -> LLVMValueRef) -> Self
+	{
+		ValueIter::from_links(Links::new(cur, last, step, Some(step_back)))
+	}
+	
+	fn from_links(links: Links<LLVMValue>) -> Self
 	{
 		ValueIter {
-			cur: cur,
-			step: step,
+			links: links,
 			marker1: ::std::marker::PhantomData,
 			marker2: ::std::marker::PhantomData
 		}
@@ -512,13 +1062,78 @@ impl<'a, T: From<LLVMValueRef>> Iterator for ValueIter<'a, T>
 
   fn next(&mut self) -> Option<T> 
   {
-    let old: LLVMValueRef = self.cur;
+    self.links.next().map(|value| value.into())
+  }
+}
+
+impl<'a, T: From<LLVMValueRef>> DoubleEndedIterator for ValueIter<'a, T> 
+{
+  fn next_back(&mut self) -> Option<T> 
+  {
+    self.links.next_back().map(|value| value.into())
+  }
+}
+
+
+/// An edge from a value to one of its users.
+pub struct Use;
+native_ref!(&Use = LLVMUseRef);
+
+impl Use 
+{
+  /// Returns the value that has the used value as an operand.
+  pub fn get_user(&self) -> &Value 
+  {
+    unsafe { core::LLVMGetUser(self.into()) }.into()
+  }
+  
+  /// Returns the value that is being used.
+  pub fn get_used_value(&self) -> &Value 
+  {
+    unsafe { core::LLVMGetUsedValue(self.into()) }.into()
+  }
+}
+
+
+/// An iterator through the uses of a value.
+///
+/// This walks the use list as it is, so rewriting uses while iterating may skip some.
+#[derive(Copy, Clone)]
+pub struct Uses<'a> {
+  cur    : LLVMUseRef,
+  marker : ::std::marker::PhantomData<&'a ()>,
+}
+
+impl<'a> Iterator for Uses<'a> 
+{
+  type Item = &'a Use;
+  
+  fn next(&mut self) -> Option<&'a Use> 
+  {
+    let old = self.cur;
     
     if !old.is_null() {
-      self.cur = unsafe { (self.step)(old) };
+      self.cur = unsafe { core::LLVMGetNextUse(old) };
       Some(old.into())
     } else {
       None
     }
   }
-}
\ No newline at end of file
+}
+
+
+/// An iterator through the users of a value.
+#[derive(Copy, Clone)]
+pub struct Users<'a> {
+  uses: Uses<'a>
+}
+
+impl<'a> Iterator for Users<'a> 
+{
+  type Item = &'a Value;
+  
+  fn next(&mut self) -> Option<&'a Value> 
+  {
+    self.uses.next().map(Use::get_user)
+  }
+}
@@ -0,0 +1,201 @@
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use cbox::CBox;
+use ffi::analysis::{self, LLVMVerifierFailureAction};
+use ffi::{core, LLVMLinkage};
+use ffi::prelude::{LLVMModuleRef, LLVMValueRef};
+
+use module::Module;
+use value::Function;
+
+
+/// The problems the verifier found in one function.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FunctionError
+{
+  /// The name of the function.
+  pub name: String,
+  /// What the verifier reported about the function.
+  pub message: String
+}
+
+/// The problems the verifier found in a module or function.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VerifierError
+{
+  message: String,
+  module: String,
+  functions: Vec<FunctionError>
+}
+
+impl VerifierError
+{
+  /// Returns everything the verifier reported, as one message.
+  pub fn message(&self) -> &str
+  {
+    &self.message
+  }
+
+  /// Returns the problems found in each function that failed verification.
+  ///
+  /// This is empty if the only problems are outside of function bodies.
+  pub fn functions(&self) -> &[FunctionError]
+  {
+    &self.functions
+  }
+
+  /// Returns the problems found outside of function bodies, such as in globals or
+  /// metadata, which is empty if there are none.
+  pub fn module_errors(&self) -> &str
+  {
+    &self.module
+  }
+}
+
+impl fmt::Display for VerifierError
+{
+  fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result
+  {
+    if self.functions.is_empty() {
+      return fmt.write_str(&self.message)
+    }
+    for (index, func) in self.functions.iter().enumerate() {
+      if index > 0 {
+        fmt.write_str("\n")?;
+      }
+      write!(fmt, "in function {}:\n{}", func.name, func.message.trim_end())?;
+    }
+    if !self.module.is_empty() {
+      write!(fmt, "\n{}", self.module.trim_end())?;
+    }
+    Ok(())
+  }
+}
+
+impl Error for VerifierError
+{
+  fn description(&self) -> &str
+  {
+    &self.message
+  }
+}
+
+
+/// Verify `module`, returning the verifier's message if it is broken.
+fn verify_raw(module: LLVMModuleRef) -> Option<String>
+{
+  unsafe {
+    let mut error = mem::uninitialized();
+    let action = LLVMVerifierFailureAction::LLVMReturnStatusAction;
+    let broken = analysis::LLVMVerifyModule(module, action, &mut error) == 1;
+    let message = CBox::<str>::new(error);
+    if broken {
+      Some(String::from(&*message))
+    } else {
+      None
+    }
+  }
+}
+
+/// Returns true if `func` is broken, without finding out why.
+fn is_broken(func: LLVMValueRef) -> bool
+{
+  let action = LLVMVerifierFailureAction::LLVMReturnStatusAction;
+  unsafe { analysis::LLVMVerifyFunction(func, action) == 1 }
+}
+
+/// Turn the function into a declaration by deleting its body.
+unsafe fn strip_body(func: LLVMValueRef)
+{
+  let mut block = core::LLVMGetFirstBasicBlock(func);
+  while !block.is_null() {
+    let mut instr = core::LLVMGetFirstInstruction(block);
+    while !instr.is_null() {
+      let next = core::LLVMGetNextInstruction(instr);
+      core::LLVMReplaceAllUsesWith(instr, core::LLVMGetUndef(core::LLVMTypeOf(instr)));
+      core::LLVMInstructionEraseFromParent(instr);
+      instr = next;
+    }
+    block = core::LLVMGetNextBasicBlock(block);
+  }
+  while !core::LLVMGetFirstBasicBlock(func).is_null() {
+    core::LLVMDeleteBasicBlock(core::LLVMGetFirstBasicBlock(func));
+  }
+  core::LLVMSetLinkage(func, LLVMLinkage::LLVMExternalLinkage);
+}
+
+/// Verify a copy of `module` where only the function named `keep` has a body, or none do
+/// if it is `None`, and return what the verifier reported.
+fn verify_isolated(module: LLVMModuleRef, keep: Option<&str>) -> String
+{
+  unsafe {
+    let clone = core::LLVMCloneModule(module);
+    let mut other = core::LLVMGetFirstFunction(clone);
+    while !other.is_null() {
+      let other_func: &Function = other.into();
+      if Some(other_func.get_name()) != keep && core::LLVMCountBasicBlocks(other) > 0 {
+        strip_body(other);
+      }
+      other = core::LLVMGetNextFunction(other);
+    }
+    let message = verify_raw(clone);
+    core::LLVMDisposeModule(clone);
+    message.unwrap_or_default()
+  }
+}
+
+/// Find out what is wrong with `func` by verifying a copy of its module where every other
+/// function is only declared.
+///
+/// The verifier checks function bodies before anything else in a module, so the
+/// `module_errors` it reports about the rest of the module are cut off the end.
+fn isolate(func: &Function, module_errors: &str) -> FunctionError
+{
+  let name = func.get_name().to_owned();
+  let module = unsafe { core::LLVMGetGlobalParent(func.into()) };
+  let mut message = verify_isolated(module, Some(&name));
+  if message.ends_with(module_errors) {
+    let len = message.len() - module_errors.len();
+    message.truncate(len);
+  }
+  FunctionError {
+    name: name,
+    message: message
+  }
+}
+
+/// Verify every function in `module`, then the module itself.
+pub fn verify_module(module: &Module) -> Result<(), VerifierError>
+{
+  let message = match verify_raw(module.into()) {
+    Some(message) => message,
+    None => return Ok(())
+  };
+  let module_errors = verify_isolated(module.into(), None);
+  let functions = module.into_iter()
+    .filter(|&func| func.get_entry().is_some() && is_broken(func.into()))
+    .map(|func| isolate(func, &module_errors))
+    .collect();
+  Err(VerifierError {
+    message: message,
+    module: module_errors,
+    functions: functions
+  })
+}
+
+/// Verify the body of `func`.
+pub fn verify_function(func: &Function) -> Result<(), VerifierError>
+{
+  if !is_broken(func.into()) {
+    return Ok(())
+  }
+  let module = unsafe { core::LLVMGetGlobalParent(func.into()) };
+  let error = isolate(func, &verify_isolated(module, None));
+  Err(VerifierError {
+    message: error.message.clone(),
+    module: String::new(),
+    functions: vec![error]
+  })
+}
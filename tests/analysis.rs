@@ -0,0 +1,153 @@
+extern crate llvm;
+
+use llvm::*;
+use std::{env, fs};
+
+/// Builds `sum(n)`, which adds `j` for every `j < i` for every `i < n` using two nested loops.
+fn build_nested_loops<'a>(ctx: &'a Context, module: &'a Module) -> &'a Function {
+  let func = module.add_function("sum", Type::get::<fn(u64) -> u64>(ctx));
+  let entry = func.append("entry");
+  let outer = func.append("outer");
+  let inner = func.append("inner");
+  let outer_latch = func.append("outer_latch");
+  let exit = func.append("exit");
+  
+  let builder = Builder::new(ctx);
+  builder.position_at_end(entry);
+  let i = builder.create_alloca(Type::get::<u64>(ctx));
+  let j = builder.create_alloca(Type::get::<u64>(ctx));
+  builder.create_store(0u64.compile(ctx), i);
+  builder.create_br(outer);
+  
+  builder.position_at_end(outer);
+  builder.create_store(0u64.compile(ctx), j);
+  let cond = builder.create_cmp(builder.create_load(Type::get::<u64>(ctx), i), &func[0], Predicate::LessThan);
+  builder.create_cond_br(cond, inner, Some(exit));
+  
+  builder.position_at_end(inner);
+  let next_j = builder.create_add(builder.create_load(Type::get::<u64>(ctx), j), 1u64.compile(ctx));
+  builder.create_store(next_j, j);
+  let cond = builder.create_cmp(next_j, builder.create_load(Type::get::<u64>(ctx), i), Predicate::LessThan);
+  builder.create_cond_br(cond, inner, Some(outer_latch));
+  
+  builder.position_at_end(outer_latch);
+  let next_i = builder.create_add(builder.create_load(Type::get::<u64>(ctx), i), 1u64.compile(ctx));
+  builder.create_store(next_i, i);
+  builder.create_br(outer);
+  
+  builder.position_at_end(exit);
+  builder.create_ret(builder.create_load(Type::get::<u64>(ctx), j));
+  module.verify().unwrap();
+  func
+}
+
+#[test]
+fn test_dominators() {
+  let ctx = Context::new();
+  let module = Module::new("analysis", &ctx);
+  let func = build_nested_loops(&ctx, &module);
+  let blocks: Vec<_> = func.basic_blocks().collect();
+  let (entry, outer, inner, outer_latch, exit) = (blocks[0], blocks[1], blocks[2], blocks[3], blocks[4]);
+  
+  let dom = DominatorTree::new(func);
+  assert_eq!(vec![entry], dom.roots());
+  assert_eq!(None, dom.idom(entry));
+  assert_eq!(Some(outer), dom.idom(exit));
+  assert_eq!(Some(inner), dom.idom(outer_latch));
+  assert!(dom.dominates(outer, outer_latch));
+  assert!(!dom.dominates(inner, exit));
+  assert!(dom.frontier(inner).contains(&outer));
+  assert!(dom.frontier(inner).contains(&inner));
+  
+  let post_dom = DominatorTree::new_post(func);
+  assert_eq!(vec![exit], post_dom.roots());
+  assert_eq!(Some(exit), post_dom.idom(outer));
+  assert!(post_dom.dominates(outer, entry));
+}
+
+#[test]
+fn test_loops() {
+  let ctx = Context::new();
+  let module = Module::new("analysis", &ctx);
+  let func = build_nested_loops(&ctx, &module);
+  let blocks: Vec<_> = func.basic_blocks().collect();
+  let (entry, outer, inner, outer_latch, exit) = (blocks[0], blocks[1], blocks[2], blocks[3], blocks[4]);
+  
+  let mut analyses = FunctionAnalyses::new(func);
+  let loops = analyses.loops();
+  assert_eq!(2, loops.loops().len());
+  
+  let outer_loop = loops.loops()[0];
+  assert!(outer_loop.header() == outer);
+  assert_eq!(&[outer_latch], outer_loop.latches());
+  assert_eq!(&[exit], outer_loop.exits());
+  assert_eq!(1, outer_loop.depth());
+  
+  let inner_loop = loops.loop_for(inner).unwrap();
+  assert!(inner_loop.header() == inner);
+  assert_eq!(2, inner_loop.depth());
+  assert!(loops.parent(inner_loop).unwrap().header() == outer);
+  assert_eq!(0, loops.loop_depth(entry));
+  assert!(loops.is_header(outer));
+}
+
+#[test]
+fn test_call_graph() {
+  let ctx = Context::new();
+  let module = Module::new("calls", &ctx);
+  let sig = Type::get::<fn() -> ()>(&ctx);
+  let main = module.add_function("main", sig);
+  let ping = module.add_function("ping", sig);
+  let pong = module.add_function("pong", sig);
+  let unused = module.add_function("unused", sig);
+  let callback = module.add_function("callback", sig);
+  
+  let builder = Builder::new(&ctx);
+  for &(func, callee) in &[(main, ping), (ping, pong), (pong, ping), (unused, callback)] {
+    builder.position_at_end(func.append("entry"));
+    builder.create_call(callee, &[]);
+    builder.create_ret_void();
+  }
+  builder.position_at_end(callback.append("entry"));
+  builder.create_ret_void();
+  module.add_global_constant("callbacks", Value::new_array(callback.get_type(), &[&**callback]));
+  module.verify().unwrap();
+  
+  let graph = CallGraph::new(&module);
+  assert!(graph.callees(main) == vec![ping]);
+  assert!(graph.callers(ping) == vec![main, pong]);
+  assert!(graph.is_address_taken(callback));
+  assert!(!graph.is_address_taken(ping));
+  assert!(graph.reachable(&[main]) == vec![main, ping, pong]);
+  
+  let sccs = graph.sccs();
+  let cycle = sccs.iter().position(|scc| scc.len() == 2).unwrap();
+  let main_scc = sccs.iter().position(|scc| scc == &vec![main]).unwrap();
+  assert!(cycle < main_scc);
+  
+  assert_eq!(1, module.retain_reachable(&["main", "callbacks"]));
+  assert!(module.get_function("unused").is_none());
+  assert!(module.get_function("callback").is_some());
+  assert!(module.get_global("callbacks").is_some());
+  module.verify().unwrap();
+  
+  assert_eq!(2, module.retain_reachable(&["main"]));
+  assert!(module.get_function("main").is_some());
+}
+
+#[test]
+fn test_retain_aliased() {
+  let ctx = Context::new();
+  let path = env::temp_dir().join("llvm_rs_aliased.ll");
+  fs::write(&path, "define void @main() {\n  ret void\n}\n\
+                    define void @target() {\n  ret void\n}\n\
+                    define void @dead() {\n  ret void\n}\n\
+                    @alias = alias void (), ptr @target\n").unwrap();
+  let module = Module::parse_ir(&ctx, path.to_str().unwrap()).unwrap();
+  fs::remove_file(&path).unwrap();
+  
+  assert_eq!(1, module.retain_reachable(&["main"]));
+  assert!(module.get_function("dead").is_none());
+  assert!(module.get_function("target").is_some());
+  module.verify().unwrap();
+}
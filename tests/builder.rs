@@ -33,11 +33,12 @@ pub fn test_cond_br() {
   builder.create_br(merge_bb);
   
   builder.position_at_end(merge_bb);
-  let ret_val = builder.create_load(local);
+  let ret_val = builder.create_load(Type::get::<u64>(&ctx), local);
   builder.create_ret(ret_val);
   
   module.verify().unwrap();
-  let ee = JitEngine::new(&module, JitOptions {opt_level: 0}).unwrap();
+  let ee = JitEngine::new(module, JitOptions::new().opt_level(0)).unwrap();
+  let func = ee.find_function("fib").unwrap();
   ee.with_function(func, |fib: extern fn(u64) -> u64| {
       for i in 0..10 {
         if i < 5 {
@@ -74,11 +75,11 @@ pub fn test_phi() {
   builder.create_cond_br(cond, then_bb, Some(else_bb));
   
   builder.position_at_end(then_bb);
-  let eight = builder.create_load(local1);
+  let eight = builder.create_load(Type::get::<u64>(&ctx), local1);
   builder.create_br(merge_bb);
   
   builder.position_at_end(else_bb);
-  let sixteen = builder.create_load(local2);
+  let sixteen = builder.create_load(Type::get::<u64>(&ctx), local2);
   builder.create_br(merge_bb);
   
   builder.position_at_end(merge_bb);
@@ -89,7 +90,8 @@ pub fn test_phi() {
   
   module.verify().unwrap();
   
-  let ee = JitEngine::new(&module, JitOptions {opt_level: 0}).unwrap();
+  let ee = JitEngine::new(module, JitOptions::new().opt_level(0)).unwrap();
+  let func = ee.find_function("fib").unwrap();
   ee.with_function(func, |fib: extern fn(u64) -> u64| {
       for i in 0..10 {
         if i < 5 {
@@ -99,4 +101,47 @@ pub fn test_phi() {
         }
       }
   });
-}
\ No newline at end of file
+}
+
+#[test]
+pub fn test_verify_function() {
+  let ctx = Context::new();
+  let module = Module::new("broken", &ctx);
+  let good = module.add_function("good", Type::get::<fn() -> u64>(&ctx));
+  let bad = module.add_function("bad", Type::get::<fn() -> u64>(&ctx));
+  
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(good.append("entry"));
+  builder.create_ret(1u64.compile(&ctx));
+  builder.position_at_end(bad.append("entry"));
+  builder.create_ret_void();
+  let worse = module.add_function("worse", Type::get::<fn() -> u64>(&ctx));
+  builder.position_at_end(worse.append("entry"));
+  builder.create_ret_void();
+  
+  assert!(good.verify().is_ok());
+  let err = bad.verify().unwrap_err();
+  assert_eq!(err.functions().len(), 1);
+  assert_eq!(err.functions()[0].name, "bad");
+  
+  let err = module.verify().unwrap_err();
+  let names: Vec<&str> = err.functions().iter().map(|func| &func.name[..]).collect();
+  assert_eq!(names, vec!["bad", "worse"]);
+  assert!(err.module_errors().is_empty());
+  let message = format!("{}", err);
+  assert!(message.starts_with("in function bad:\n"));
+  assert!(message.contains("\nin function worse:\n"));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic]
+pub fn test_verify_on_terminate() {
+  let ctx = Context::new();
+  let module = Module::new("broken", &ctx);
+  let func = module.add_function("bad", Type::get::<fn() -> u64>(&ctx));
+  let builder = Builder::new(&ctx);
+  builder.set_verify_functions(true);
+  builder.position_at_end(func.append("entry"));
+  builder.create_ret_void();
+}
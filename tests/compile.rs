@@ -0,0 +1,65 @@
+extern crate llvm;
+
+use llvm::*;
+
+#[test]
+fn test_array_compile() {
+  let ctx = Context::new();
+  let array = [1u32, 2, 3, 4, 5, 6, 7, 8, 9, 10].compile(&ctx);
+  assert_eq!("[10 x i32]", format!("{}", array.get_type()));
+  assert_eq!(Type::get::<[u32; 10]>(&ctx), array.get_type());
+  
+  let vector = Simd([1.0f64, 2.0]).compile(&ctx);
+  assert_eq!("<2 x double>", format!("{}", vector.get_type()));
+}
+
+#[test]
+fn test_global_array() {
+  let ctx = Context::new();
+  let module = Module::new("tables", &ctx);
+  let table: Vec<u8> = (0..64).collect();
+  let global = module.add_global_array("table", &table);
+  assert_eq!("[64 x i8]", format!("{}", global.get_initializer().get_type()));
+  module.verify().unwrap();
+}
+
+#[test]
+fn test_constant_folding() {
+  let ctx = Context::new();
+  let i32_t = Type::i32_ty(&ctx);
+  let sum = Value::const_add(Value::const_int(i32_t, 40, false), 2i32.compile(&ctx)).unwrap();
+  assert!(sum.is_constant());
+  assert_eq!(format!("{}", 42i32.compile(&ctx)), format!("{}", sum));
+  
+  assert!(Value::null(i32_t).is_null());
+  assert!(Value::new_undef(i32_t).is_undef());
+  assert_eq!(format!("{}", (-1i32).compile(&ctx)), format!("{}", Value::all_ones(i32_t)));
+  
+  let lt = Value::const_cmp(1i32.compile(&ctx), 2i32.compile(&ctx), Predicate::LessThan).unwrap();
+  assert_eq!(format!("{}", true.compile(&ctx)), format!("{}", lt));
+  
+  let lanes = Simd([1.0f32, 4.0]).compile(&ctx);
+  let lt = Value::const_cmp(lanes, Simd([2.0f32, 3.0]).compile(&ctx), Predicate::LessThan).unwrap();
+  assert_eq!("<2 x i1>", format!("{}", lt.get_type()));
+
+  let ptr_t = Type::pointer_ty(i32_t);
+  let eq = Value::const_ucmp(Value::null(ptr_t), Value::null(ptr_t), Predicate::Equal).unwrap();
+  assert_eq!(format!("{}", true.compile(&ctx)), format!("{}", eq));
+  assert!(Value::const_add(Value::null(ptr_t), Value::null(ptr_t)).is_none());
+}
+
+#[test]
+fn test_decompile() {
+  let ctx = Context::new();
+  assert_eq!(Some(-5i32), i32::decompile((-5i32).compile(&ctx)));
+  assert_eq!(Some(250u8), u8::decompile(250u8.compile(&ctx)));
+  assert_eq!(None, u64::decompile(250u8.compile(&ctx)));
+  assert_eq!(Some(1.5f64), f64::decompile(1.5f64.compile(&ctx)));
+  assert_eq!(Some('λ'), char::decompile('λ'.compile(&ctx)));
+  assert_eq!(Some("hello"), <&str>::decompile("hello".compile(&ctx)));
+  assert_eq!(Some(""), <&str>::decompile("".compile(&ctx)));
+  assert_eq!(Some((1u8, 2.0f32, false)), <(u8, f32, bool)>::decompile((1u8, 2.0f32, false).compile(&ctx)));
+  
+  let folded = Value::const_mul(6u64.compile(&ctx), 7u64.compile(&ctx)).unwrap();
+  assert_eq!(Some(42u64), u64::decompile(folded));
+}
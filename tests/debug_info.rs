@@ -0,0 +1,34 @@
+extern crate llvm;
+
+use llvm::*;
+
+#[test]
+fn test_debug_info() {
+  let ctx = Context::new();
+  let module = Module::new("debug", &ctx);
+  let func = module.add_function("square", Type::get::<fn(i64) -> i64>(&ctx));
+  let entry = func.append("entry");
+  
+  let mut debug = DebugInfoBuilder::new(&module, 4);
+  let file = debug.create_file("square.src", "/tmp");
+  let unit = debug.create_compile_unit(file, Language::C, "llvm-rs", false);
+  let int = debug.create_basic_type("int", 64, Encoding::Signed);
+  let ty = debug.create_function_type(file, Some(int), &[int]);
+  let scope = debug.create_function(unit, func, file, 1, ty);
+  let param = debug.create_parameter_variable(scope, "x", 1, file, 1, int);
+  
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(entry);
+  builder.set_current_debug_location(1, 1, scope);
+  let local = builder.create_alloca(Type::get::<i64>(&ctx));
+  builder.create_store(&func[0], local);
+  debug.declare(local, param, entry, 1, 1, scope);
+  builder.set_current_debug_location(2, 3, scope);
+  let value = builder.create_load(Type::get::<i64>(&ctx), local);
+  builder.create_ret(builder.create_mul(value, value));
+  builder.clear_current_debug_location();
+  
+  debug.finalize();
+  module.verify().unwrap();
+  assert!(format!("{}", module).contains("!DISubprogram(name: \"square\""));
+}
@@ -0,0 +1,49 @@
+extern crate llvm;
+
+use llvm::*;
+
+#[test]
+fn test_cfg_dot() {
+  let ctx = Context::new();
+  let module = Module::new("simple", &ctx);
+  let func = module.add_function("pick", Type::get::<fn(u64) -> u64>(&ctx));
+  let entry = func.append("entry");
+  let zero = func.append("zero");
+  let other = func.append("other");
+  
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(entry);
+  builder.create_switch(&func[0], other, &[(0u64.compile(&ctx), zero)]);
+  builder.position_at_end(zero);
+  builder.create_ret(1u64.compile(&ctx));
+  builder.position_at_end(other);
+  builder.create_ret(&func[0]);
+  module.verify().unwrap();
+  
+  let mut out = Vec::new();
+  func.to_dot(&mut out).unwrap();
+  let dot = String::from_utf8(out).unwrap();
+  assert!(dot.starts_with("digraph \"pick\" {"));
+  assert!(dot.contains("bb0 -> bb2 [label=\"default\"];"));
+  assert!(dot.contains("bb0 -> bb1 [label=\"i64 0\"];"));
+  assert!(dot.contains("ret i64 1"));
+}
+
+#[test]
+fn test_call_graph_dot() {
+  let ctx = Context::new();
+  let module = Module::new("simple", &ctx);
+  let sin = module.add_function("llvm.sin.f64", Type::get::<fn(f64) -> f64>(&ctx));
+  let func = module.add_function("twice_sin", Type::get::<fn(f64) -> f64>(&ctx));
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(func.append("entry"));
+  let once = builder.create_call(sin, &[&func[0]]);
+  builder.create_ret(builder.create_call(sin, &[once]));
+  
+  let mut out = Vec::new();
+  module.call_graph_dot(&mut out).unwrap();
+  let dot = String::from_utf8(out).unwrap();
+  assert!(dot.contains("f0 [label=\"llvm.sin.f64\", style=dashed];"));
+  assert_eq!(1, dot.matches("f1 -> f0;").count());
+  assert!(!dot.contains("indirect"));
+}
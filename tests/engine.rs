@@ -1,9 +1,10 @@
 extern crate libc;
+#[macro_use]
 extern crate llvm;
 
 use libc::c_void;
 use llvm::*;
-use std::mem;
+use std::{env, fs, mem};
 
 pub extern "C" fn test_func3(x: f64) -> f64 {
   x
@@ -13,15 +14,16 @@ pub extern "C" fn test_func3(x: f64) -> f64 {
 fn add_global_mapping() {
   let ctx = Context::new();
   let module = Module::new("test_func_find", &ctx); 
-  module.verify().expect("verifying the module failed...");
- 
-  let ee = JitEngine::new(&module, JitOptions {opt_level: 0}).unwrap();
   
   let ret_ty    = Type::get::<f64>(&ctx);
   let param_tys = vec![Type::get::<f64>(&ctx)];
   let fn_ty     = Type::function_ty(ret_ty, &param_tys);
-  let function  = module.add_function("test_func3", fn_ty);
-  let fn_ptr: *const c_void = unsafe { mem::transmute(test_func3) };
+  module.add_function("test_func3", fn_ty);
+  module.verify().expect("verifying the module failed...");
+ 
+  let ee = JitEngine::new(module, JitOptions::new().opt_level(0)).unwrap();
+  let function = ee.find_function("test_func3").unwrap();
+  let fn_ptr = test_func3 as *const c_void;
   unsafe { ee.add_global_mapping(function, fn_ptr); }  
   
 	let f: fn(f64) -> f64;
@@ -31,4 +33,348 @@ fn add_global_mapping() {
   };
   
   assert_eq!(98.0f64, f(98.0f64));
-}
\ No newline at end of file
+}
+
+#[test]
+fn jit_options() {
+  assert_eq!(JitOptions::default(), JitOptions::new());
+  assert!(JitOptions::new().fast_isel);
+  
+  let ctx = Context::new();
+  let module = Module::new("jit_options", &ctx);
+  let func = module.add_function("double", Type::get::<fn(u64) -> u64>(&ctx));
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(func.append("entry"));
+  builder.create_ret(builder.create_add(&func[0], &func[0]));
+  module.verify().unwrap();
+  
+  let options = JitOptions::new()
+    .opt_level(1)
+    .code_model(CodeModel::Default)
+    .frame_pointers(true)
+    .fast_isel(false)
+    .cpu("generic");
+  let ee = JitEngine::new(module, options).unwrap();
+  let func = ee.find_function("double").unwrap();
+  ee.with_function(func, |double: extern fn(u64) -> u64| {
+    assert_eq!(double(21), 42);
+  });
+}
+
+#[test]
+fn page_memory_manager() {
+  use std::cell::RefCell;
+  use std::rc::Rc;
+  
+  let ctx = Context::new();
+  let module = Module::new("page_memory_manager", &ctx);
+  let func = module.add_function("triple", Type::get::<fn(u64) -> u64>(&ctx));
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(func.append("entry"));
+  builder.create_ret(builder.create_mul(&func[0], 3u64.compile(&ctx)));
+  module.verify().unwrap();
+  
+  let manager = Rc::new(RefCell::new(PageMemoryManager::new()));
+  let options = JitOptions::new().memory_manager(manager.clone());
+  let ee = JitEngine::new(module, options).unwrap();
+  let func = ee.find_function("triple").unwrap();
+  ee.with_function(func, |triple: extern fn(u64) -> u64| {
+    assert_eq!(triple(14), 42);
+  });
+  
+  let manager = manager.borrow();
+  assert!(manager.code_size() > 0);
+  assert!(manager.allocated() >= manager.code_size() + manager.data_size());
+}
+
+#[test]
+fn test_page_memory_manager_alignment() {
+  let mut manager = PageMemoryManager::new();
+  let align = 1 << 20;
+  let ptr = manager.allocate_data_section(16, align, 0, ".data", false);
+  assert!(!ptr.is_null());
+  assert_eq!(0, ptr as usize % align as usize);
+  unsafe { *ptr.offset(15) = 1 };
+  assert_eq!(16, manager.data_size());
+  manager.finalize_memory().unwrap();
+}
+
+pub extern "C" fn add_ten(x: u64) -> u64 {
+  x + 10
+}
+
+pub extern "C" fn add_twenty(x: u64) -> u64 {
+  x + 20
+}
+
+#[test]
+fn resolve_symbols() {
+  let ctx = Context::new();
+  let module = Module::new("resolve_symbols", &ctx);
+  let ty = Type::get::<fn(u64) -> u64>(&ctx);
+  let add_ten_decl = module.add_function("add_ten", ty);
+  let add_twenty_decl = module.add_function("add_twenty", ty);
+  let func = module.add_function("add_thirty", ty);
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(func.append("entry"));
+  let ten = builder.create_call(add_ten_decl, &[&func[0]]);
+  builder.create_ret(builder.create_call(add_twenty_decl, &[ten]));
+  module.verify().unwrap();
+
+  let ee = JitEngine::new(module, JitOptions::new().opt_level(0)).unwrap();
+  let func = ee.find_function("add_thirty").unwrap();
+  let mut resolver = symbols![add_ten];
+  assert_eq!(ee.resolve_symbols(&mut resolver).unwrap_err().names(), &["add_twenty".to_owned()]);
+
+  resolver.set_fallback(|name| if name == "add_twenty" {
+    Some(add_twenty as *const c_void)
+  } else {
+    None
+  });
+  ee.resolve_symbols(&mut resolver).unwrap();
+  assert!(resolver.resolve("malloc").is_none());
+  assert!(resolver.search_process().resolve("malloc").is_some());
+  ee.with_function(func, |add_thirty: extern fn(u64) -> u64| {
+    assert_eq!(add_thirty(12), 42);
+  });
+}
+
+fn define_value<'a>(module: &'a Module, value: u64) -> &'a Function {
+  let ctx = module.get_context();
+  let func = module.add_function("value", Type::get::<fn() -> u64>(ctx));
+  let builder = Builder::new(ctx);
+  builder.position_at_end(func.append("entry"));
+  builder.create_ret(value.compile(ctx));
+  func
+}
+
+#[test]
+fn redefine() {
+  let ctx = Context::new();
+  let module = Module::new("redefine", &ctx);
+  let value = define_value(&module, 1);
+  let func = module.add_function("call_value", Type::get::<fn() -> u64>(&ctx));
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(func.append("entry"));
+  builder.create_ret(builder.create_call(value, &[]));
+  module.verify().unwrap();
+  
+  let mut ee = JitEngine::new(module, JitOptions::new().opt_level(0)).unwrap();
+  assert!(ee.redefine("value", Module::new("unknown", &ctx)).is_err());
+  ee.make_redefinable("value").unwrap();
+  // The engine is borrowed mutably to redefine the function, so this can't be a handle.
+  let call_value = unsafe {
+    let func = ee.find_function("call_value").unwrap();
+    ee.get_typed_function::<extern "C" fn() -> u64>(func).unwrap()
+  };
+  assert_eq!(call_value(), 1);
+  
+  let second = Module::new("second", &ctx);
+  define_value(&second, 2);
+  ee.redefine("value", second).unwrap();
+  assert_eq!(call_value(), 2);
+  
+  let third = Module::new("third", &ctx);
+  define_value(&third, 3);
+  ee.redefine("value", third).unwrap();
+  assert_eq!(call_value(), 3);
+  assert_eq!(ee.release_old_versions("value"), 1);
+  assert_eq!(ee.release_old_versions("value"), 0);
+}
+
+#[test]
+fn get_typed_function() {
+  let ctx = Context::new();
+  let module = Module::new("get_typed_function", &ctx);
+  let func = module.add_function("mul_add", Type::get::<fn(u64, u64, u64) -> u64>(&ctx));
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(func.append("entry"));
+  builder.create_ret(builder.create_add(builder.create_mul(&func[0], &func[1]), &func[2]));
+  module.verify().unwrap();
+  
+  let ee = JitEngine::new(module, JitOptions::new().opt_level(0)).unwrap();
+  let func = ee.find_function("mul_add").unwrap();
+  unsafe {
+    assert_eq!(ee.get_typed_function::<extern "C" fn(u64, u64) -> u64>(func).unwrap_err(),
+               SignatureError::ParamCount { expected: 2, found: 3 });
+    match ee.get_typed_function::<extern "C" fn(u64, u32, u64) -> u64>(func) {
+      Err(SignatureError::Param { index: 1, .. }) => (),
+      _ => panic!("the second parameter should not match")
+    }
+    match ee.get_typed_function::<extern "C" fn(u64, u64, u64) -> f64>(func) {
+      Err(SignatureError::Return { .. }) => (),
+      _ => panic!("the return type should not match")
+    }
+    let mul_add = ee.get_typed_function::<extern "C" fn(u64, u64, u64) -> u64>(func).unwrap();
+    assert_eq!(mul_add(4, 10, 2), 42);
+  }
+}
+
+#[test]
+fn call_typed_function() {
+  let ctx = Context::new();
+  let module = Module::new("call_typed_function", &ctx);
+  let func = module.add_function("scale", Type::get::<fn(*const f64, f64) -> f64>(&ctx));
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(func.append("entry"));
+  let value = builder.create_load(Type::get::<f64>(&ctx), &func[0]);
+  builder.create_ret(builder.create_mul(value, &func[1]));
+  module.verify().unwrap();
+  
+  let ee = JitEngine::new(module, JitOptions::new().opt_level(0)).unwrap();
+  let func = ee.find_function("scale").unwrap();
+  assert_eq!(func.get_signature().get_params(), vec![Type::get::<*const f64>(&ctx), Type::get::<f64>(&ctx)]);
+  assert_eq!(func.get_signature().get_return(), Type::get::<f64>(&ctx));
+  let scale = unsafe { ee.get_typed_function::<extern "C" fn(*const f64, f64) -> f64>(func).unwrap() };
+  let value = 1.5f64;
+  assert_eq!(scale(&value, 4.0), 6.0);
+}
+
+#[test]
+fn jit_function() {
+  let ctx = Context::new();
+  let module = Module::new("jit_function", &ctx);
+  let func = module.add_function("select", Type::get::<fn(bool, u32, u32) -> u32>(&ctx));
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(func.append("entry"));
+  builder.create_ret(builder.create_select(&func[0], &func[1], &func[2]));
+  module.verify().unwrap();
+  
+  let ee = JitEngine::new(module, JitOptions::new().opt_level(0)).unwrap();
+  let func = ee.find_function("select").unwrap();
+  assert!(ee.get_jit_function::<extern "C" fn(u32, u32) -> u32>(func).is_err());
+  let select = ee.get_jit_function::<extern "C" fn(bool, u32, u32) -> u32>(func).unwrap();
+  let copy = select;
+  assert_eq!(select.call(true, 42, 7), 42);
+  assert_eq!(copy.call(false, 7, 42), 42);
+  
+  let other = Module::new("other", &ctx);
+  let func = other.add_function("select", Type::get::<fn(bool, u32, u32) -> u32>(&ctx));
+  assert_eq!(ee.get_jit_function::<extern "C" fn(bool, u32, u32) -> u32>(func).unwrap_err(),
+             SignatureError::NotInEngine);
+}
+
+#[test]
+fn module_ownership() {
+  let ctx = Context::new();
+  let module = Module::new("first", &ctx);
+  define_value(&module, 1);
+  let mut ee = JitEngine::new(module, JitOptions::new().opt_level(0)).unwrap();
+  
+  let module = Module::new("second", &ctx);
+  let func = module.add_function("other", Type::get::<fn() -> u64>(&ctx));
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(func.append("entry"));
+  builder.create_ret(7u64.compile(&ctx));
+  let handle = ee.add_module(module);
+  
+  let other = ee.find_function("other").unwrap();
+  assert_eq!(ee.get_module_handle(other), Some(handle));
+  let first = ee.get_module_handle(ee.find_function("value").unwrap()).unwrap();
+  assert!(first != handle);
+  
+  let module = ee.remove_module(handle).unwrap();
+  assert!(module.get_function("other").is_some());
+  assert!(ee.find_function("other").is_none());
+  assert!(ee.remove_module(handle).is_err());
+  // Adding the same module again gives it a new handle, so the old one stays invalid.
+  assert!(ee.add_module(module) != handle);
+  assert!(ee.remove_module(handle).is_err());
+  
+  let interp = Interpreter::new(ee.remove_module(first).unwrap(), ()).unwrap();
+  let value = interp.find_function("value").unwrap();
+  assert_eq!(u64::from_generic(interp.run_function(value, &[]), &ctx), 1);
+}
+
+pub extern "C" fn clamp(x: i64, max: i64) -> i64 {
+  if x > max { max } else { x }
+}
+
+#[test]
+fn interpreter_externals() {
+  let ctx = Context::new();
+  let module = Module::new("script", &ctx);
+  let host = module.add_function("clamp", Type::get::<fn(i64, i64) -> i64>(&ctx));
+  let func = module.add_function("run", Type::get::<fn(i64) -> i64>(&ctx));
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(func.append("entry"));
+  builder.create_ret(builder.create_call(host, &[&func[0], 10i64.compile(&ctx)]));
+  module.add_function("abort", Type::get::<fn() -> ()>(&ctx));
+  module.verify().unwrap();
+  
+  let interp = Interpreter::new(module, ()).unwrap();
+  let func = interp.find_function("run").unwrap();
+  match interp.run_restricted(func, &[3i64.to_generic(&ctx)]) {
+    Err(ExternalError::Unregistered(names)) => assert_eq!(names, vec!["clamp".to_owned(), "abort".to_owned()]),
+    _ => panic!("the declarations should not be bound")
+  }
+  
+  interp.add_external("clamp", clamp as extern "C" fn(i64, i64) -> i64);
+  interp.add_external("abort", clamp as extern "C" fn(i64, i64) -> i64);
+  match interp.bind_externals() {
+    Err(ExternalError::Signature { name, .. }) => assert_eq!(name, "abort"),
+    _ => panic!("abort should not match its declaration")
+  }
+  
+  extern "C" fn ignore() {}
+  interp.add_external("abort", ignore as extern "C" fn());
+  let result = interp.run_restricted(func, &[42i64.to_generic(&ctx)]).unwrap();
+  assert_eq!(i64::from_generic(result, &ctx), 10);
+  // The declarations only have other names while the function runs.
+  assert!(interp.find_function("clamp").is_some());
+}
+
+#[test]
+fn generic_values() {
+  let ctx = Context::new();
+  let module = Module::new("generic_values", &ctx);
+  let func = module.add_function("bump", Type::get::<fn(*const u64, u64) -> u64>(&ctx));
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(func.append("entry"));
+  let old = builder.create_load(Type::get::<u64>(&ctx), &func[0]);
+  builder.create_store(builder.create_add(old, &func[1]), &func[0]);
+  builder.create_ret(old);
+  module.verify().unwrap();
+  
+  let interp = Interpreter::new(module, ()).unwrap();
+  let func = interp.find_function("bump").unwrap();
+  let mut counter = 40u64;
+  
+  match interp.run_function_checked(func, &[]) {
+    Err(error) => assert_eq!(error, SignatureError::ParamCount { expected: 0, found: 2 }),
+    _ => panic!("the function should not be run without arguments")
+  }
+  match interp.run_function_checked(func, &(&mut counter as *mut u64, 2u32).to_generic_args(&ctx)) {
+    Err(SignatureError::Param { index: 1, .. }) => (),
+    _ => panic!("a u32 should not be passed as a u64")
+  }
+  match interp.run_function_checked(func, &(2u64, 2u64).to_generic_args(&ctx)) {
+    Err(SignatureError::Param { index: 0, .. }) => (),
+    _ => panic!("an integer should not be passed as a pointer")
+  }
+  
+  let args = (&mut counter as *mut u64, 2u64).to_generic_args(&ctx);
+  let result = interp.run_function_checked(func, &args).unwrap();
+  assert_eq!(result.int_width(), Some(64));
+  assert_eq!(u64::from_generic(result, &ctx), 40);
+  assert_eq!(counter, 42);
+  
+  let ptr = GenericValue::from_pointer(&mut counter as *mut u64);
+  assert_eq!(ptr.to_pointer::<u64>(), &mut counter as *mut u64);
+  assert_eq!(ptr.int_width(), None);
+  
+  let wide = (-5i128).to_generic(&ctx);
+  assert_eq!(wide.int_width(), Some(128));
+  assert_eq!(i128::from_generic(wide, &ctx), -5);
+  assert_eq!(u128::from_generic(7u128.to_generic(&ctx), &ctx), 7);
+  assert_eq!(u128::try_from_generic(7u128.to_generic(&ctx), &ctx), Some(7));
+  
+  // Only the low 64 bits of a wide integer returned by a function can be read.
+  let path = env::temp_dir().join("llvm_rs_generic_wide.ll");
+  fs::write(&path, "define i128 @wide() {\n  ret i128 -5\n}\n").unwrap();
+  let module = Module::parse_ir(&ctx, path.to_str().unwrap()).unwrap();
+  let interp = Interpreter::new(module, ()).unwrap();
+  let result = interp.run_function(interp.find_function("wide").unwrap(), &[]);
+  assert_eq!(result.int_width(), Some(128));
+  assert_eq!(i128::try_from_generic(result, &ctx), None);
+}
@@ -0,0 +1,163 @@
+extern crate llvm;
+
+use llvm::*;
+use std::{env, fs};
+
+#[test]
+fn test_instruction_introspection() {
+  let ctx = Context::new();
+  let module = Module::new("simple", &ctx);
+  let func = module.add_function("add", Type::get::<fn(u64, u64) -> u64>(&ctx));
+  let entry = func.append("entry");
+  
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(entry);
+  let sum = builder.create_add(&func[0], &func[1]);
+  let ret = builder.create_ret(sum);
+  module.verify().unwrap();
+  
+  let sum = Instruction::cast(sum).unwrap();
+  assert_eq!(Opcode::Add, sum.opcode());
+  assert_eq!(2, sum.num_operands());
+  assert!(sum.operands().eq(vec![&*func[0], &*func[1]]));
+  assert!(sum.parent() == Some(entry));
+  assert!(!sum.is_terminator());
+  
+  let ret = ReturnInst::cast(ret).unwrap();
+  assert!(ret.is_terminator());
+  assert!(ret.get_value().unwrap() == &**sum);
+  assert!(CallInst::cast(ret).is_none());
+  
+  sum.set_operand(1, &func[0]);
+  assert!(sum.get_operand(1).unwrap() == &*func[0]);
+}
+
+#[test]
+fn test_newer_opcodes() {
+  let ctx = Context::new();
+  let path = env::temp_dir().join("llvm_rs_opcodes.ll");
+  fs::write(&path, "define float @neg(float %x) {\n  %y = fneg float %x\n  %z = freeze float %y\n  ret float %z\n}\n").unwrap();
+  let module = Module::parse_ir(&ctx, path.to_str().unwrap()).unwrap();
+  fs::remove_file(&path).unwrap();
+  
+  let entry = module.get_function("neg").unwrap().get_entry().unwrap();
+  let opcodes: Vec<Opcode> = entry.instructions().map(|instr| instr.opcode()).collect();
+  assert_eq!(vec![Opcode::FNeg, Opcode::Freeze, Opcode::Ret], opcodes);
+}
+
+#[test]
+fn test_replace_all_uses_with() {
+  let ctx = Context::new();
+  let module = Module::new("simple", &ctx);
+  let func = module.add_function("double", Type::get::<fn(u64) -> u64>(&ctx));
+  let entry = func.append("entry");
+  
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(entry);
+  let sum = builder.create_add(&func[0], &func[0]);
+  let ret = builder.create_ret(sum);
+  
+  assert_eq!(2, func[0].uses().count());
+  assert!(sum.users().eq(vec![ret]));
+  
+  builder.position_at(entry, sum);
+  let product = builder.create_mul(&func[0], 2u64.compile(&ctx));
+  sum.replace_all_uses_with(product);
+  assert!(sum.is_unused());
+  unsafe { Instruction::cast(sum).unwrap().erase_from_parent() };
+  assert_eq!(2, entry.instructions().count());
+  
+  module.verify().unwrap();
+  assert!(product.users().eq(vec![ret]));
+}
+
+#[test]
+fn test_block_iteration() {
+  let ctx = Context::new();
+  let module = Module::new("simple", &ctx);
+  let func = module.add_function("pick", Type::get::<fn(bool) -> u64>(&ctx));
+  let entry = func.append("entry");
+  let then_bb = func.append("then");
+  let else_bb = func.append("else");
+  
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(entry);
+  let br = builder.create_cond_br(&func[0], then_bb, Some(else_bb));
+  builder.position_at_end(then_bb);
+  builder.create_ret(1u64.compile(&ctx));
+  builder.position_at_end(else_bb);
+  builder.create_ret(2u64.compile(&ctx));
+  
+  let names: Vec<_> = func.basic_blocks().map(|bb| bb.get_name().unwrap()).collect();
+  assert_eq!(vec!["entry", "then", "else"], names);
+  let names: Vec<_> = func.basic_blocks().rev().map(|bb| bb.get_name().unwrap()).collect();
+  assert_eq!(vec!["else", "then", "entry"], names);
+  assert_eq!(3, func.num_basic_blocks());
+  
+  assert!(&**entry.get_terminator().unwrap() == br);
+  assert_eq!(1, entry.instructions().count());
+  assert!(then_bb.get_next().unwrap() == else_bb);
+  assert!(then_bb.get_previous().unwrap() == entry);
+  assert!(func.get_entry().unwrap().first_instruction() == entry.last_instruction());
+}
+
+#[test]
+fn test_cfg_queries() {
+  let ctx = Context::new();
+  let module = Module::new("simple", &ctx);
+  let func = module.add_function("pick", Type::get::<fn(bool) -> u64>(&ctx));
+  let entry = func.append("entry");
+  let then_bb = func.append("then");
+  let merge_bb = func.append("merge");
+  let dead_bb = func.append("dead");
+  
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(entry);
+  builder.create_cond_br(&func[0], then_bb, Some(merge_bb));
+  builder.position_at_end(then_bb);
+  let one = builder.create_add(1u64.compile(&ctx), 0u64.compile(&ctx));
+  builder.create_br(merge_bb);
+  builder.position_at_end(merge_bb);
+  let phi = builder.create_phi(Type::get::<u64>(&ctx), "result");
+  phi.add_incoming(2u64.compile(&ctx), entry);
+  phi.add_incoming(one, then_bb);
+  builder.create_ret(phi);
+  builder.position_at_end(dead_bb);
+  builder.create_br(merge_bb);
+  
+  assert_eq!(vec![then_bb, merge_bb], entry.successors());
+  assert_eq!(3, merge_bb.predecessors().len());
+  assert!(!dead_bb.is_reachable());
+  assert_eq!(vec![dead_bb], func.unreachable_blocks());
+  
+  let tail = then_bb.split_at(Instruction::cast(one).unwrap(), "then_tail");
+  assert_eq!(vec![tail], then_bb.successors());
+  assert_eq!(vec![then_bb], tail.predecessors());
+  assert!(merge_bb.predecessors().contains(&tail));
+  
+  let pre = entry.insert_before("pre");
+  builder.position_at_end(pre);
+  builder.create_br(entry);
+  pre.move_before(entry);
+  module.verify().unwrap();
+}
+
+#[test]
+fn test_split_last_block() {
+  let ctx = Context::new();
+  let module = Module::new("simple", &ctx);
+  let func = module.add_function("double", Type::get::<fn(u64) -> u64>(&ctx));
+  let entry = func.append("entry");
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(entry);
+  let sum = builder.create_add(&func[0], &func[0]);
+  builder.create_ret(sum);
+  
+  let tail = entry.split_at(Instruction::cast(sum).unwrap(), "tail");
+  assert_eq!(2, func.num_basic_blocks());
+  assert!(entry.get_next().unwrap() == tail);
+  assert_eq!(vec![tail], entry.successors());
+  assert_eq!(2, tail.instructions().count());
+  assert!(tail.is_reachable());
+  module.verify().unwrap();
+}
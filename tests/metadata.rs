@@ -0,0 +1,55 @@
+extern crate llvm;
+
+use llvm::*;
+use std::os::raw::c_char;
+
+#[test]
+fn test_metadata_string() {
+  let ctx = Context::new();
+  let text = MDString::new(&ctx, "hello");
+  assert_eq!(text.get_string(), "hello");
+  assert!(MDString::cast(text).is_some());
+  assert!(MDNode::cast(text).is_none());
+}
+
+#[test]
+fn test_instruction_metadata() {
+  let ctx = Context::new();
+  let module = Module::new("metadata", &ctx);
+  let func = module.add_function("load", Type::get::<fn(*const c_char) -> i8>(&ctx));
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(func.append("entry"));
+  let load = builder.create_load(Type::get::<i8>(&ctx), &func[0]);
+  builder.create_ret(load);
+  
+  let range = ctx.metadata_kind_id("range");
+  assert_eq!(range, ctx.metadata_kind_id("range"));
+  assert!(!load.has_metadata());
+  assert!(load.get_metadata(range).is_none());
+  
+  let node = MDNode::range(&ctx, 0i8.compile(&ctx), 10i8.compile(&ctx));
+  load.set_metadata(range, node);
+  assert!(load.has_metadata());
+  assert!(load.get_metadata(range) == Some(node));
+  assert_eq!(node.num_operands(), 2);
+  module.verify().unwrap();
+  
+  load.remove_metadata(range);
+  assert!(!load.has_metadata());
+}
+
+#[test]
+fn test_named_metadata() {
+  let ctx = Context::new();
+  let module = Module::new("metadata", &ctx);
+  assert!(module.get_named_metadata("tags").is_empty());
+  
+  let first = MDNode::new(&ctx, &[&**MDString::new(&ctx, "first")]);
+  let second = MDNode::branch_weights(&ctx, &[1, 99]);
+  module.add_named_metadata_operand("tags", first);
+  module.add_named_metadata_operand("tags", second);
+  let nodes = module.get_named_metadata("tags");
+  assert_eq!(nodes.len(), 2);
+  assert!(nodes[0] == first && nodes[1] == second);
+  assert_eq!(second.num_operands(), 3);
+}
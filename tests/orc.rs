@@ -0,0 +1,64 @@
+extern crate llvm;
+
+use llvm::*;
+
+#[test]
+fn test_cross_module_calls() {
+  let ctx = Context::new();
+  let ty = Type::get::<fn(u64) -> u64>(&ctx);
+  
+  let first = Module::new("first", &ctx);
+  let func = first.add_function("add_two", ty);
+  let add_one = first.add_function("add_one", ty);
+  let builder = Builder::new(&ctx);
+  builder.position_at_end(func.append("entry"));
+  let once = builder.create_call(add_one, &[&func[0]]);
+  builder.create_ret(builder.create_call(add_one, &[once]));
+  first.verify().unwrap();
+  
+  // `add_one` is only defined by a module added after the one that calls it.
+  let second = Module::new("second", &ctx);
+  let func = second.add_function("add_one", ty);
+  builder.position_at_end(func.append("entry"));
+  builder.create_ret(builder.create_add(&func[0], 1u64.compile(&ctx)));
+  second.verify().unwrap();
+  
+  let mut engine = OrcEngine::new(JitOptions::new().opt_level(0)).unwrap();
+  let first = engine.add_module(first).unwrap();
+  engine.add_module(second).unwrap();
+  assert!(engine.find_function("missing").is_none());
+  assert!(engine.get_symbol_address("missing").is_none());
+  assert!(engine.get_symbol_address("add_one").is_some());
+  {
+    let add_two = engine.find_function("add_two").unwrap();
+    assert_eq!(engine.get_module_handle(add_two), Some(first));
+    engine.with_function(add_two, |add_two: extern fn(u64) -> u64| {
+      assert_eq!(add_two(40), 42);
+    });
+  }
+  
+  let module = engine.remove_module(first).unwrap();
+  assert!(module.get_function("add_two").is_some());
+  assert!(engine.remove_module(first).is_err());
+  assert!(engine.find_function("add_two").is_none());
+  assert!(engine.get_symbol_address("add_two").is_none());
+}
+
+#[test]
+fn test_duplicate_symbols() {
+  let ctx = Context::new();
+  let ty = Type::get::<fn() -> u64>(&ctx);
+  let builder = Builder::new(&ctx);
+  let engine = OrcEngine::new(JitOptions::new()).unwrap();
+  for _ in 0..2 {
+    let module = Module::new("answer", &ctx);
+    let func = module.add_function("answer", ty);
+    builder.position_at_end(func.append("entry"));
+    builder.create_ret(42u64.compile(&ctx));
+    if engine.find_function("answer").is_none() {
+      engine.add_module(module).unwrap();
+    } else {
+      assert!(engine.add_module(module).is_err());
+    }
+  }
+}
@@ -0,0 +1,98 @@
+extern crate llvm;
+
+use llvm::*;
+
+/// Builds `add_one(x)`, which stores `x + 1` on the stack before returning it.
+fn build_add_one<'a>(ctx: &'a Context, module: &'a Module) -> &'a Function {
+  let func = module.add_function("add_one", Type::get::<fn(u64) -> u64>(ctx));
+  let builder = Builder::new(ctx);
+  builder.position_at_end(func.append("entry"));
+  let local = builder.create_alloca(Type::get::<u64>(ctx));
+  builder.create_store(builder.create_add(&func[0], 1u64.compile(ctx)), local);
+  builder.create_ret(builder.create_load(Type::get::<u64>(ctx), local));
+  module.verify().unwrap();
+  func
+}
+
+fn has_alloca(func: &Function) -> bool {
+  func.basic_blocks().any(|block| block.instructions().any(|instr| AllocaInst::cast(instr).is_some()))
+}
+
+#[test]
+fn test_pass_manager() {
+  let ctx = Context::new();
+  let module = Module::new("passes", &ctx);
+  let func = build_add_one(&ctx, &module);
+  
+  let mut passes = PassManager::new();
+  passes.add(Pass::PromoteMemoryToRegister).add_all(&[Pass::InstructionCombining, Pass::GVN]);
+  assert!(passes.run(&module));
+  assert!(!has_alloca(func));
+  assert_eq!(passes.timings().len(), 1);
+  assert_eq!(passes.timings()[0].name, "mem2reg, instcombine, gvn");
+  assert!(!passes.run(&module));
+  module.verify().unwrap();
+}
+
+#[test]
+fn test_function_pass_manager() {
+  let ctx = Context::new();
+  let module = Module::new("passes", &ctx);
+  let func = build_add_one(&ctx, &module);
+  
+  let passes = FunctionPassManager::new(&module, &[Pass::PromoteMemoryToRegister]);
+  assert!(passes.run(func));
+  assert!(!has_alloca(func));
+  assert!(!passes.run(func));
+}
+
+#[test]
+#[should_panic]
+fn test_function_pass_manager_module_pass() {
+  let ctx = Context::new();
+  let module = Module::new("passes", &ctx);
+  FunctionPassManager::new(&module, &[Pass::GlobalDCE]);
+}
+
+/// Renames every function it runs over, and records the names it saw.
+struct Rename(std::rc::Rc<std::cell::RefCell<Vec<String>>>);
+
+impl FunctionPass for Rename {
+  fn name(&self) -> &str {
+    "rename"
+  }
+  
+  fn run(&mut self, func: &Function) -> bool {
+    self.0.borrow_mut().push(func.get_name().to_owned());
+    let name = format!("{}_renamed", func.get_name());
+    func.set_name(&name);
+    true
+  }
+}
+
+#[test]
+fn test_custom_passes() {
+  let ctx = Context::new();
+  let module = Module::new("passes", &ctx);
+  let func = build_add_one(&ctx, &module);
+  module.add_function("external", Type::get::<fn() -> u64>(&ctx));
+  
+  let seen = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+  let mut passes = PassManager::new();
+  passes.add(Pass::PromoteMemoryToRegister)
+        .add_function_pass(Rename(seen.clone()))
+        .add(Pass::GVN);
+  assert!(passes.run(&module));
+  assert_eq!(*seen.borrow(), vec!["add_one".to_owned()]);
+  assert_eq!(func.get_name(), "add_one_renamed");
+  
+  let names: Vec<&str> = passes.timings().iter().map(|timing| &timing.name[..]).collect();
+  assert_eq!(names, vec!["mem2reg", "rename", "gvn"]);
+  assert!(passes.timings()[1].changed);
+  
+  let mut passes = PassManager::new();
+  passes.inline_threshold(100).add_standard(2, 0).add_function_pass(Rename(seen.clone()));
+  assert!(module.optimize_with(&mut passes));
+  assert_eq!(func.get_name(), "add_one_renamed_renamed");
+  assert_eq!(passes.timings().len(), 2);
+}